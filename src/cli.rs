@@ -1,19 +1,158 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use clap_complete::Shell;
-use tp::config::{Error, Session};
+use tp::config::{Error, Format, Session};
 
 #[derive(Parser, Debug)]
 #[command(about = "A simple tmux session loader")]
 pub enum Cli {
     /// Create a new session file
-    New { session_name: String },
-    /// Load a session
+    New {
+        session_name: String,
+        /// The session file format
+        #[arg(long, value_enum, default_value_t = SessionFormat::Yaml)]
+        format: SessionFormat,
+        /// Overwrite the session file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Open a session file in `$VISUAL` or `$EDITOR`, checking it once saved
+    Edit {
+        session: String,
+        /// Create the session file if it doesn't exist
+        #[arg(long)]
+        new: bool,
+        /// The format of the session file created with `--new`
+        #[arg(long, value_enum, default_value_t = SessionFormat::Yaml, requires = "new")]
+        format: SessionFormat,
+        /// Template variables, as `name=value`
+        #[arg(last = true, value_parser = parse_variable)]
+        vars: Vec<(String, String)>,
+    },
+    /// Load a session, the local session file (`.`) by default
     Load {
+        #[arg(default_value = Session::LOCAL_NAME)]
+        session: String,
+        /// Kill the partially created session if loading fails
+        #[arg(long)]
+        rollback: bool,
+        /// Print the tmux commands instead of running them
+        #[arg(long)]
+        dry_run: bool,
+        /// Create the windows and panes missing from a running session
+        #[arg(long)]
+        sync: bool,
+        /// Kill the windows and panes of a running session missing from its file
+        #[arg(long, requires = "sync")]
+        prune: bool,
+        /// Do not attach to the session when run outside tmux
+        #[arg(long)]
+        detach: bool,
+        /// Do not switch the current client to the session when run inside tmux
+        #[arg(long)]
+        no_switch: bool,
+        /// Template variables, as `name=value`
+        #[arg(last = true, value_parser = parse_variable)]
+        vars: Vec<(String, String)>,
+    },
+    /// Check session files for problems, exiting with an error if any is found
+    Validate {
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        session: Option<String>,
+        /// Check every session file
+        #[arg(long)]
+        all: bool,
+        /// Fail on warnings too
+        #[arg(long)]
+        strict: bool,
+        /// Template variables, as `name=value`
+        #[arg(last = true, value_parser = parse_variable)]
+        vars: Vec<(String, String)>,
+    },
+    /// Stop a running session
+    #[command(alias = "kill")]
+    Stop {
+        #[arg(
+            value_parser = parser_session_config,
+            required_unless_present = "all",
+            conflicts_with = "all"
+        )]
+        session: Option<Session>,
+        /// Stop every running session that has a session file
+        #[arg(long)]
+        all: bool,
+        /// Seconds to wait for interrupted commands to exit before killing
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+        /// Template variables, as `name=value`
+        #[arg(last = true, value_parser = parse_variable)]
+        vars: Vec<(String, String)>,
+    },
+    /// Delete a session file
+    #[command(alias = "remove")]
+    Rm { session: String },
+    /// Rename a session file and the session it defines
+    #[command(alias = "rename")]
+    Mv {
+        session: String,
+        /// The new session name
+        new_name: String,
+        /// Overwrite the session file of the new name if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Copy a session file to a new session
+    #[command(alias = "copy")]
+    Cp {
+        session: String,
+        /// The name of the new session
+        new_name: String,
+        /// Overwrite the session file of the new name if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// List sessions
+    List {
+        /// Show the file each session is loaded from
+        #[arg(long)]
+        paths: bool,
+        /// Show whether each session is running, its attached clients, windows,
+        /// directory and when it was loaded
+        #[arg(long)]
+        long: bool,
+        /// Only list the sessions running in tmux
+        #[arg(long, conflicts_with = "stopped")]
+        running: bool,
+        /// Only list the sessions not running in tmux
+        #[arg(long)]
+        stopped: bool,
+        /// The output format
+        #[arg(long, value_enum, default_value_t = ListFormat::Text)]
+        format: ListFormat,
+    },
+    /// Save a running tmux session as a session file
+    Freeze {
+        /// The name of the running tmux session
+        tmux_session: String,
+        /// The session file name, defaults to the tmux session name
+        #[arg(long)]
+        name: Option<String>,
+        /// Overwrite the session file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Export a session as a standalone script
+    Export {
+        /// The script format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Sh)]
+        format: ExportFormat,
         #[arg(value_parser = parser_session_config)]
         session: Session,
+        /// Template variables, as `name=value`
+        #[arg(last = true, value_parser = parse_variable)]
+        vars: Vec<(String, String)>,
     },
-    /// List sessions
-    List,
+    /// Print the JSON Schema of session files
+    Schema,
     /// Generate shel completions
     Completions {
         /// The shell to generate completions for
@@ -22,10 +161,48 @@ pub enum Cli {
     },
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// POSIX shell script
+    Sh,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ListFormat {
+    /// One session per line, or a table with `--long`
+    Text,
+    /// An array of the sessions and their status
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SessionFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl From<SessionFormat> for Format {
+    fn from(format: SessionFormat) -> Self {
+        match format {
+            SessionFormat::Yaml => Self::Yaml,
+            SessionFormat::Toml => Self::Toml,
+            SessionFormat::Json => Self::Json,
+        }
+    }
+}
+
 fn parser_session_config(value: &str) -> Result<Session, Error> {
     Session::load_from_name(value)
 }
 
+fn parse_variable(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("invalid variable `{value}`, expected `name=value`"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
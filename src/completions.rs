@@ -30,25 +30,19 @@ pub fn generate(shell: Shell) -> Result<(), Error> {
 }
 
 fn replace(haystack: &mut String, needle: &str, replacement: &str) -> Result<(), Error> {
-    if let Some(index) = haystack.find(needle) {
-        haystack.replace_range(index..index + needle.len(), replacement);
+    if haystack.contains(needle) {
+        *haystack = haystack.replace(needle, replacement);
         Ok(())
     } else {
         Err(Error::ReplacementNotFound(needle.to_string()))
     }
 }
 
+// Every positional argument named `session` completes configured sessions,
+// dropping the `(local)` marker `tp list` prints after the local session.
 const ZSH_COMPLETION_REPLACEMENTS: &[(&str, &str)] = &[(
-    r#"(load)
-_arguments "${_arguments_options[@]}" : \
-'-h[Print help]' \
-'--help[Print help]' \
-':session:_default' \"#,
-    r#"(load)
-_arguments "${_arguments_options[@]}" : \
-'-h[Print help]' \
-'--help[Print help]' \
-':session:($(tp list))' \"#,
+    r#":session:_default'"#,
+    r#":session:(${${(f)"$(tp list)"}%% *})'"#,
 )];
 
 #[cfg(test)]
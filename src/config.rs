@@ -1,6 +1,20 @@
+use crate::template;
+use schemars::{JsonSchema, Schema};
 use serde::{Deserialize, Serialize};
-use std::{env, fs, io, path::PathBuf};
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    fs, io, mem,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
+use yaml_rust2::{
+    parser::{Event, Parser},
+    scanner::TScalarStyle,
+};
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -8,45 +22,447 @@ pub enum Error {
     UnableToLoad(#[from] io::Error),
     #[error("parser error: {0}")]
     UnableToParseConfig(#[from] serde_yaml::Error),
+    #[error("parser error: {0}")]
+    UnableToParseToml(#[from] toml::de::Error),
+    #[error("unable to write TOML: {0}")]
+    UnableToWriteToml(#[from] toml::ser::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("invalid session directory")]
     InvalidSessionDirectory,
+    #[error("undefined variables: {}", .0.join(", "))]
+    UndefinedVariables(Vec<String>),
+    #[error("session files extend or include each other: {}", .0.join(" -> "))]
+    InheritanceCycle(Vec<String>),
+    #[error(
+        "session `{name}` is defined by several files: {}",
+        paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    DuplicateSession { name: String, paths: Vec<PathBuf> },
+    #[error("invalid session name `{0}`")]
+    InvalidSessionName(String),
+    #[error("no local session file in {} or its parents", .0.display())]
+    LocalSessionNotFound(PathBuf),
+    #[error("session file {} already exists", .0.display())]
+    SessionExists(PathBuf),
+    #[error("the local session file cannot be moved, copy it with `tp cp . <name>`")]
+    LocalSessionMove,
+}
+
+/// A session file found by [`Session::list`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionFile {
+    /// The name the session is loaded with.
+    pub name: String,
+    pub path: PathBuf,
+    /// Whether the file was found from the current directory rather than in
+    /// the sessions directory.
+    pub local: bool,
+    /// The other files of the same directory defining the session in another
+    /// format, which make loading it fail.
+    pub conflicts: Vec<PathBuf>,
+}
+
+impl SessionFile {
+    /// The error loading the session fails with, if several files define it.
+    pub fn conflict(&self) -> Option<Error> {
+        (!self.conflicts.is_empty()).then(|| Error::DuplicateSession {
+            name: self.name.clone(),
+            paths: [&self.path]
+                .into_iter()
+                .chain(&self.conflicts)
+                .cloned()
+                .collect(),
+        })
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The formats a session file can be written in, told apart by extension.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Format {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    pub const ALL: [Self; 3] = [Self::Yaml, Self::Toml, Self::Json];
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Yaml => &["yaml", "yml"],
+            Self::Toml => &["toml"],
+            Self::Json => &["json"],
+        }
+    }
+
+    /// The extension of the files `tp` writes.
+    pub fn extension(self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension))
+    }
+
+    // Every format is read into a YAML value, so merging and deserializing
+    // the session work the same way whatever the file format.
+    fn parse(self, content: &str) -> Result<Value, Error> {
+        let value = match self {
+            Self::Yaml => serde_yaml::from_str(content)?,
+            Self::Toml => toml::from_str(content)?,
+            Self::Json => serde_json::from_str(content)?,
+        };
+        Ok(value)
+    }
+
+    fn serialize(self, session: &impl Serialize) -> Result<String, Error> {
+        let content = match self {
+            Self::Yaml => serde_yaml::to_string(session)?,
+            Self::Toml => toml::to_string(session)?,
+            Self::Json => serde_json::to_string_pretty(session)? + "\n",
+        };
+        Ok(content)
+    }
+
+    // Set the session name of the file `content` to `name`, editing only the
+    // `name` field where possible so comments and formatting are kept.
+    fn rename(self, content: &str, name: &str) -> Result<String, Error> {
+        let mut value = self.parse(content)?;
+        let renamed = match self {
+            Self::Yaml | Self::Json => name_span(content).map(|span| {
+                let name = match self {
+                    Self::Json => serde_json::to_string(name)?,
+                    _ => serde_yaml::to_string(name)?.trim_end().to_string(),
+                };
+                Ok::<_, Error>(format!(
+                    "{}{name}{}",
+                    &content[..span.start],
+                    &content[span.end..]
+                ))
+            }),
+            Self::Toml => content
+                .parse::<toml_edit::DocumentMut>()
+                .ok()
+                .map(|mut document| {
+                    // Keep the spacing and comment around the old name.
+                    let mut value = toml_edit::Value::from(name);
+                    if let Some(old) = document.get("name").and_then(toml_edit::Item::as_value) {
+                        *value.decor_mut() = old.decor().clone();
+                    }
+                    document["name"] = toml_edit::Item::Value(value);
+                    Ok(document.to_string())
+                }),
+        };
+        if let Some(content) = renamed {
+            return content;
+        }
+
+        if let Value::Mapping(mapping) = &mut value {
+            mapping.insert("name".into(), name.into());
+        }
+        self.serialize(&value)
+    }
+}
+
+// The span of the value of the top-level `name` field of a YAML or JSON file,
+// when it's a scalar written on one line.
+fn name_span(content: &str) -> Option<Range<usize>> {
+    let mut parser = Parser::new_from_str(content);
+    let mut depth = 0;
+    let mut is_key = true;
+    let mut found = false;
+    loop {
+        let (event, mark) = parser.next_token().ok()?;
+        let is_entry = match event {
+            Event::StreamEnd => return None,
+            Event::MappingStart(..) | Event::SequenceStart(..) => {
+                if depth == 1 && found {
+                    return None;
+                }
+                depth += 1;
+                false
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                depth -= 1;
+                depth == 1
+            }
+            Event::Scalar(text, style, ..) if depth == 1 => {
+                if found {
+                    // Markers count characters, not bytes.
+                    let (start, _) = content.char_indices().nth(mark.index())?;
+                    let len = scalar_len(&content[start..], style)?;
+                    return Some(start..start + len);
+                }
+                found = is_key && text == "name";
+                true
+            }
+            Event::Alias(_) if depth == 1 => {
+                if found {
+                    return None;
+                }
+                true
+            }
+            _ => false,
+        };
+        if is_entry {
+            is_key = !is_key;
+        }
+    }
+}
+
+fn scalar_len(text: &str, style: TScalarStyle) -> Option<usize> {
+    let quote = match style {
+        TScalarStyle::Plain => {
+            let line = text.lines().next()?;
+            let value = line.split(" #").next()?;
+            return Some(value.trim_end().len());
+        }
+        TScalarStyle::SingleQuoted => '\'',
+        TScalarStyle::DoubleQuoted => '"',
+        _ => return None,
+    };
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        // `\"` in double quotes, `''` in single quotes.
+        let escaped = match quote {
+            '"' => c == '\\',
+            _ => c == quote && text[i + 1..].starts_with(quote),
+        };
+        if escaped {
+            chars.next();
+        } else if c == '\n' {
+            return None;
+        } else if c == quote {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+/// A tmux session, as described by a session file.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Session {
+    /// The name of the tmux session.
     pub name: String,
+    /// A session file whose settings this one builds upon.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Session files whose windows are appended to this one's.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Default values of the template variables.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, Scalar>,
+    /// The directory windows and panes start in.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub directory: Option<PathBuf>,
+    /// Environment variables of the session.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, Scalar>,
+    /// Commands sent to every pane before its own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<String>,
+    /// tmux options of the session.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, Scalar>,
+    /// tmux global options, set when the session is loaded.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub global_options: BTreeMap<String, Scalar>,
+    /// Commands run as the session is created, attached, detached or stopped.
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// The windows of the session, in order. An empty one has a single pane.
     #[serde(default = "default_windows")]
+    #[schemars(with = "Vec<Option<Window>>")]
     pub windows: Vec<Window>,
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+/// Shell commands run from the session directory as the session changes.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Run before a new session is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_create: Option<String>,
+    /// Run before switching to a session that is already running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_attach: Option<String>,
+    /// Run by tmux when the last client detaches from the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_detach: Option<String>,
+    /// Run by `tp stop` before the session is killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_stop: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A tmux window.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Window {
+    /// The window name, left to tmux when missing.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// The directory the panes start in, instead of the session one.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub directory: Option<PathBuf>,
+    /// Environment variables of the panes, on top of the session ones.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, Scalar>,
+    /// Commands sent to every pane after the session ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre: Vec<String>,
+    /// A tmux layout name, like `tiled`, or a layout string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// tmux options of the window.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, Scalar>,
+    /// The panes of the window, in order. An empty one uses the defaults.
     #[serde(default = "default_panes")]
+    #[schemars(with = "Vec<Option<Pane>>")]
     pub panes: Vec<Pane>,
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+/// A tmux pane.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Pane {
-    #[serde(default)]
+    /// Select this pane once the session is created.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[schemars(extend("default" = false))]
     pub focus: bool,
+    /// The directory the pane starts in, instead of the window one.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub directory: Option<PathBuf>,
+    /// Environment variables of the pane, on top of the window ones.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, Scalar>,
+    /// A command sent to the pane.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub command: Option<String>,
+    /// Commands sent to the pane after `command`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+    /// Run the commands as the pane process instead of typing them in a shell.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[schemars(extend("default" = false))]
+    pub exec: bool,
+    /// Keep the pane open once its `exec` process exits.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[schemars(extend("default" = false))]
+    pub remain_on_exit: bool,
+    /// How the pane is split from its target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<Split>,
+    /// The size of the new pane.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<Size>,
+    /// The index of the pane to split, the previous one by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<usize>,
+}
+
+/// A plain YAML value, so `on`, `5000` and `true` are all accepted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Scalar {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Integer(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// Side by side.
+    Horizontal,
+    /// One above the other.
+    Vertical,
+}
+
+/// A number of cells (`20`) or a percentage of the target pane (`30%`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "SizeValue", into = "SizeValue")]
+pub enum Size {
+    Cells(u32),
+    Percentage(u8),
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum SizeValue {
+    Number(u32),
+    Text(#[schemars(regex(pattern = r"^\s*\d+%?\s*$"))] String),
+}
+
+impl TryFrom<SizeValue> for Size {
+    type Error = String;
+
+    fn try_from(value: SizeValue) -> Result<Self, Self::Error> {
+        let text = match value {
+            SizeValue::Number(cells) => return Ok(Self::Cells(cells)),
+            SizeValue::Text(text) => text,
+        };
+        let invalid =
+            || format!("invalid size `{text}`, expected cells (`20`) or a percentage (`30%`)");
+        match text.trim().strip_suffix('%') {
+            Some(percentage) => percentage
+                .parse()
+                .ok()
+                .filter(|p| (1..=100).contains(p))
+                .map(Self::Percentage)
+                .ok_or_else(invalid),
+            None => text.trim().parse().map(Self::Cells).map_err(|_| invalid()),
+        }
+    }
+}
+
+impl From<Size> for SizeValue {
+    fn from(size: Size) -> Self {
+        match size {
+            Size::Cells(cells) => Self::Number(cells),
+            Size::Percentage(_) => Self::Text(size.to_string()),
+        }
+    }
+}
+
+impl Display for Size {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cells(cells) => write!(f, "{cells}"),
+            Self::Percentage(percentage) => write!(f, "{percentage}%"),
+        }
+    }
 }
 
 fn default_windows() -> Vec<Window> {
     vec![Window {
-        name: None,
-        directory: None,
         panes: default_panes(),
+        ..Default::default()
     }]
 }
 
@@ -54,31 +470,367 @@ fn default_panes() -> Vec<Pane> {
     vec![Pane::default()]
 }
 
+/// The files a session file builds upon.
+#[derive(Deserialize)]
+struct Parents {
+    #[serde(default)]
+    extends: Option<String>,
+    #[serde(default)]
+    include: Vec<String>,
+}
+
+/// The session files the settings of a merged session come from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Origins {
+    /// The file setting the directory of the session, if any.
+    pub directory: Option<PathBuf>,
+    /// The file each window comes from, with its index in that file.
+    pub windows: Vec<(PathBuf, usize)>,
+}
+
+impl Origins {
+    fn new(path: &Path, value: &Value) -> Self {
+        let windows = value
+            .get("windows")
+            .and_then(Value::as_sequence)
+            .map_or(0, Vec::len);
+        Self {
+            directory: value.get("directory").map(|_| path.to_path_buf()),
+            windows: (0..windows)
+                .map(|index| (path.to_path_buf(), index))
+                .collect(),
+        }
+    }
+}
+
+const MERGED_MAPS: &[&str] = &["vars", "env", "options", "global_options", "hooks"];
+
+// `session` values replace the `base` ones, except maps, which are merged, and
+// windows, which replace the base window with the same name or are appended.
+// `origins` are updated from the base ones to the merged ones.
+fn merge(base: Value, session: Value, origins: &mut Origins, session_origins: Origins) -> Value {
+    let (mut base, session) = match (base, session) {
+        (Value::Mapping(base), Value::Mapping(session)) => (base, session),
+        (_, session) => {
+            *origins = session_origins;
+            return session;
+        }
+    };
+    let Origins {
+        directory,
+        windows: mut window_origins,
+    } = session_origins;
+    if session.contains_key("directory") {
+        origins.directory = directory;
+    }
+    for (key, value) in session {
+        let merged = match (key.as_str(), base.remove(&key)) {
+            (Some("windows"), Some(Value::Sequence(windows))) => merge_windows(
+                windows,
+                value,
+                &mut origins.windows,
+                mem::take(&mut window_origins),
+            ),
+            (Some(name), Some(Value::Mapping(mut map))) if MERGED_MAPS.contains(&name) => {
+                if let Value::Mapping(value) = value {
+                    map.extend(value);
+                }
+                Value::Mapping(map)
+            }
+            (Some("windows"), _) => {
+                origins.windows = mem::take(&mut window_origins);
+                value
+            }
+            _ => value,
+        };
+        base.insert(key, merged);
+    }
+    Value::Mapping(base)
+}
+
+fn merge_windows(
+    mut windows: Vec<Value>,
+    session_windows: Value,
+    origins: &mut Vec<(PathBuf, usize)>,
+    session_origins: Vec<(PathBuf, usize)>,
+) -> Value {
+    let Value::Sequence(session_windows) = session_windows else {
+        *origins = session_origins;
+        return session_windows;
+    };
+    for (window, origin) in session_windows.into_iter().zip(session_origins) {
+        let name = window.get("name").filter(|name| !name.is_null());
+        match name.and_then(|name| windows.iter().position(|w| w.get("name") == Some(name))) {
+            Some(position) => {
+                windows[position] = window;
+                origins[position] = origin;
+            }
+            None => {
+                windows.push(window);
+                origins.push(origin);
+            }
+        }
+    }
+    Value::Sequence(windows)
+}
+
+// A local session starts in the directory holding its file, which relative
+// directories are resolved from. Templated directories are left alone.
+fn resolve_local_directory(value: &mut Value, root: &Path) {
+    let Some(mapping) = value.as_mapping_mut() else {
+        return;
+    };
+    let directory = match mapping.get("directory").and_then(Value::as_str) {
+        None => Some(root.to_path_buf()),
+        Some(directory) => resolve_relative_directory(directory, root),
+    };
+    if let Some(directory) = directory {
+        mapping.insert("directory".into(), directory.to_string_lossy().into());
+    }
+
+    let windows = mapping.get_mut("windows").and_then(Value::as_sequence_mut);
+    for window in windows.into_iter().flatten() {
+        resolve_relative_entry(window, root);
+        let panes = window.get_mut("panes").and_then(Value::as_sequence_mut);
+        for pane in panes.into_iter().flatten() {
+            resolve_relative_entry(pane, root);
+        }
+    }
+}
+
+fn resolve_relative_entry(entry: &mut Value, root: &Path) {
+    let directory = entry
+        .get("directory")
+        .and_then(Value::as_str)
+        .and_then(|directory| resolve_relative_directory(directory, root));
+    if let (Some(mapping), Some(directory)) = (entry.as_mapping_mut(), directory) {
+        mapping.insert("directory".into(), directory.to_string_lossy().into());
+    }
+}
+
+fn resolve_relative_directory(directory: &str, root: &Path) -> Option<PathBuf> {
+    let templated = directory.starts_with('~') || directory.contains(['{', '$']);
+    (!templated).then(|| root.join(directory))
+}
+
+fn render_directory(directory: &mut Option<PathBuf>, render: &mut impl FnMut(&mut String)) {
+    if let Some(mut text) = directory
+        .as_ref()
+        .and_then(|dir| dir.to_str())
+        .map(str::to_owned)
+    {
+        render(&mut text);
+        *directory = Some(text.into());
+    }
+}
+
+fn render_env(env: &mut BTreeMap<String, Scalar>, render: &mut impl FnMut(&mut String)) {
+    for value in env.values_mut() {
+        if let Scalar::String(text) = value {
+            render(text);
+        }
+    }
+}
+
+impl Pane {
+    /// The commands typed into the pane, `command` first.
+    pub fn commands(&self) -> impl Iterator<Item = &String> {
+        self.command.iter().chain(&self.commands)
+    }
+}
+
+impl Hooks {
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        [
+            &mut self.on_create,
+            &mut self.on_attach,
+            &mut self.on_detach,
+            &mut self.on_stop,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
 impl Session {
     const DEFAULT_DIR_ENV: &str = "TP_SESSIONS_DIR";
+    const PATH_ENV: &str = "TP_SESSIONS_PATH";
+    const CONFIG_HOME_ENV: &str = "XDG_CONFIG_HOME";
     const HOME_ENV: &str = "HOME";
+    const CONFIG_DIR: &str = "tp";
     const DEFAULT_DIR: &str = ".config/tp";
-    const DEFAULT_FILE_EXT: &str = "yaml";
+    const LOCAL_FILE_STEMS: [&str; 2] = [".tp", "tp"];
+    /// The name of the local session file, see [`Session::find_local`].
+    pub const LOCAL_NAME: &str = ".";
 
     pub fn load_from_name(name: impl AsRef<str>) -> Result<Self, Error> {
-        let dir = Self::default_directory().ok_or(Error::InvalidSessionDirectory)?;
-        let path = dir
-            .join(format!("{}.{}", name.as_ref(), Self::DEFAULT_FILE_EXT))
-            .canonicalize()?;
-        let content = fs::read_to_string(path)?;
-        let session = Self::load_from_string(&content)?;
-        Ok(session)
+        Ok(Self::load_with_origins(name)?.0)
     }
 
-    fn default_directory() -> Option<PathBuf> {
-        env::var(Self::DEFAULT_DIR_ENV)
-            .ok()
+    /// Load the session file `name` like [`Session::load_from_name`], with
+    /// the files its settings come from.
+    pub(crate) fn load_with_origins(name: impl AsRef<str>) -> Result<(Self, Origins), Error> {
+        let (value, origins) = Self::load_value(name.as_ref(), &mut vec![])?;
+        let session = serde_yaml::from_value(value)?;
+        Ok((session, origins))
+    }
+
+    // Read the session file `name` with the files it extends and includes
+    // merged in. `chain` holds the files being read, to report cycles.
+    fn load_value(name: &str, chain: &mut Vec<String>) -> Result<(Value, Origins), Error> {
+        if chain.iter().any(|parent| parent == name) {
+            let mut cycle = chain.clone();
+            cycle.push(name.to_string());
+            return Err(Error::InheritanceCycle(cycle));
+        }
+        chain.push(name.to_string());
+
+        let file = Self::path(name)?;
+        let path = file.canonicalize()?;
+        let content = fs::read_to_string(&path)?;
+        let format = Format::from_path(&path).unwrap_or_default();
+        let mut value = format.parse(&content)?;
+        let mut origins = Origins::new(&file, &value);
+
+        let parents: Parents = serde_yaml::from_value(value.clone())?;
+        if let Some(mapping) = value.as_mapping_mut() {
+            mapping.remove("extends");
+            mapping.remove("include");
+        }
+        if let Some(base) = &parents.extends {
+            let (base, mut base_origins) = Self::load_value(base, chain)?;
+            value = merge(base, value, &mut base_origins, origins);
+            origins = base_origins;
+        }
+        for included in &parents.include {
+            let (included, included_origins) = Self::load_value(included, chain)?;
+            let windows = included.get("windows").cloned().unwrap_or_default();
+            value = merge(
+                value,
+                Value::Mapping(Mapping::from_iter([("windows".into(), windows)])),
+                &mut origins,
+                Origins {
+                    directory: None,
+                    windows: included_origins.windows,
+                },
+            );
+        }
+
+        if name == Self::LOCAL_NAME {
+            resolve_local_directory(&mut value, path.parent().unwrap_or(Path::new("/")));
+        }
+
+        chain.pop();
+        Ok((value, origins))
+    }
+
+    /// The file session `name` is loaded from.
+    pub fn path(name: &str) -> Result<PathBuf, Error> {
+        if name == Self::LOCAL_NAME {
+            let cwd = env::current_dir()?;
+            return Self::find_local(&cwd)?.ok_or(Error::LocalSessionNotFound(cwd));
+        }
+        Self::check_name(name)?;
+        Self::find_file(&Self::directories(), name)
+    }
+
+    // Names are paths relative to a sessions directory, `work/api` being the
+    // `api` session of the `work` subdirectory.
+    fn check_name(name: &str) -> Result<(), Error> {
+        let valid = name
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+        if !valid {
+            return Err(Error::InvalidSessionName(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// The local session file nearest to `dir`, looking for `.tp.yaml` or
+    /// `tp.yaml`, in any format, up through its parents.
+    pub fn find_local(dir: &Path) -> Result<Option<PathBuf>, Error> {
+        for dir in dir.ancestors() {
+            let mut paths: Vec<PathBuf> = Self::LOCAL_FILE_STEMS
+                .iter()
+                .flat_map(|stem| Self::files(dir, stem))
+                .collect();
+            match paths.len() {
+                0 => continue,
+                1 => return Ok(paths.pop()),
+                _ => {
+                    return Err(Error::DuplicateSession {
+                        name: Self::LOCAL_NAME.to_string(),
+                        paths,
+                    });
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // The file of session `name`, whatever its format, from the first of
+    // `dirs` that has one.
+    fn find_file(dirs: &[PathBuf], name: &str) -> Result<PathBuf, Error> {
+        if dirs.is_empty() {
+            return Err(Error::InvalidSessionDirectory);
+        }
+        let paths = dirs.iter().map(|dir| Self::files(dir, name));
+        match paths.into_iter().find(|paths| !paths.is_empty()) {
+            None => {
+                let dirs: Vec<_> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no session file for `{name}` in {}", dirs.join(", ")),
+                )
+                .into())
+            }
+            Some(mut paths) if paths.len() == 1 => Ok(paths.remove(0)),
+            Some(paths) => Err(Error::DuplicateSession {
+                name: name.to_string(),
+                paths,
+            }),
+        }
+    }
+
+    fn files(dir: &Path, name: &str) -> Vec<PathBuf> {
+        Format::ALL
+            .iter()
+            .flat_map(|format| format.extensions())
+            .map(|extension| dir.join(format!("{name}.{extension}")))
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    /// The directories sessions are looked up in, from the one whose sessions
+    /// shadow the others': the `TP_SESSIONS_PATH` entries when it is set, which
+    /// replace the other sources, else `TP_SESSIONS_DIR`, else `tp` in the
+    /// user configuration directory.
+    pub fn directories() -> Vec<PathBuf> {
+        let var = |name| env::var(name).ok().filter(|value| !value.is_empty());
+        if let Some(path) = var(Self::PATH_ENV) {
+            return env::split_paths(&path)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect();
+        }
+        var(Self::DEFAULT_DIR_ENV)
             .map(PathBuf::from)
             .or_else(|| {
-                env::var(Self::HOME_ENV)
-                    .ok()
-                    .map(|home| PathBuf::from(home).join(Self::DEFAULT_DIR))
+                var(Self::CONFIG_HOME_ENV).map(|dir| PathBuf::from(dir).join(Self::CONFIG_DIR))
             })
+            .or_else(|| var(Self::HOME_ENV).map(|home| PathBuf::from(home).join(Self::DEFAULT_DIR)))
+            .into_iter()
+            .collect()
+    }
+
+    // New session files are written to the first directory.
+    fn default_directory() -> Option<PathBuf> {
+        Self::directories().into_iter().next()
+    }
+
+    /// The JSON Schema of session files, for editors to complete and check
+    /// them.
+    pub fn schema() -> Schema {
+        schemars::schema_for!(Session)
     }
 
     pub fn load_from_string(content: impl AsRef<str>) -> Result<Self, Error> {
@@ -86,7 +838,70 @@ impl Session {
         Ok(session)
     }
 
-    pub fn create(name: impl Into<String>) -> Result<PathBuf, Error> {
+    /// Fill the `{{ name }}` and `${NAME:-default}` placeholders of the session
+    /// from `variables`, then the `env` maps the placeholder is in the scope
+    /// of, then the environment, then the session `vars`.
+    pub fn interpolate(&self, variables: &BTreeMap<String, String>) -> Result<Self, Error> {
+        let lookup = |name: &str, scope: &BTreeMap<String, Scalar>| {
+            variables
+                .get(name)
+                .cloned()
+                .or_else(|| scope.get(name).map(Scalar::to_string))
+                .or_else(|| env::var(name).ok())
+                .or_else(|| self.vars.get(name).map(Scalar::to_string))
+        };
+        let mut undefined = vec![];
+        let mut session = self.clone();
+        session.for_each_template(&mut |text, scope| {
+            *text = template::render(text, |name| lookup(name, scope), &mut undefined);
+        });
+
+        if !undefined.is_empty() {
+            undefined.sort();
+            undefined.dedup();
+            return Err(Error::UndefinedVariables(undefined));
+        }
+        Ok(session)
+    }
+
+    // Each `env` map is rendered with the maps around it in scope, then is in
+    // scope itself for the rest of its session, window or pane, as the pane
+    // shells see those variables too.
+    fn for_each_template(
+        &mut self,
+        render: &mut impl FnMut(&mut String, &BTreeMap<String, Scalar>),
+    ) {
+        let mut scope = BTreeMap::new();
+        render_env(&mut self.env, &mut |text| render(text, &scope));
+        scope.extend(self.env.clone());
+        let mut render_session = |text: &mut String| render(text, &scope);
+        render_session(&mut self.name);
+        render_directory(&mut self.directory, &mut render_session);
+        self.pre.iter_mut().for_each(&mut render_session);
+        self.hooks.iter_mut().for_each(&mut render_session);
+        for window in &mut self.windows {
+            let mut scope = scope.clone();
+            render_env(&mut window.env, &mut |text| render(text, &scope));
+            scope.extend(window.env.clone());
+            let mut render_window = |text: &mut String| render(text, &scope);
+            window.name.iter_mut().for_each(&mut render_window);
+            render_directory(&mut window.directory, &mut render_window);
+            window.pre.iter_mut().for_each(&mut render_window);
+            for pane in &mut window.panes {
+                let mut scope = scope.clone();
+                render_env(&mut pane.env, &mut |text| render(text, &scope));
+                scope.extend(pane.env.clone());
+                let mut render_pane = |text: &mut String| render(text, &scope);
+                render_directory(&mut pane.directory, &mut render_pane);
+                pane.command.iter_mut().for_each(&mut render_pane);
+                pane.commands.iter_mut().for_each(&mut render_pane);
+            }
+        }
+    }
+
+    /// Write a new session file, refusing to replace the file of an existing
+    /// session unless `force` is set.
+    pub fn create(name: impl Into<String>, format: Format, force: bool) -> Result<PathBuf, Error> {
         let session = Self {
             name: name.into(),
             directory: Some(".".into()),
@@ -99,33 +914,162 @@ impl Session {
                 }],
                 ..Default::default()
             }],
+            ..Default::default()
         };
 
+        let name = session.name.clone();
+        session.save(name, format, force)
+    }
+
+    /// Write the session to the sessions directory as `<file_name>.<ext>`,
+    /// refusing to replace an existing file unless `force` is set, and to
+    /// write it at all when a file in another format already defines it.
+    pub fn save(
+        &self,
+        file_name: impl AsRef<str>,
+        format: Format,
+        force: bool,
+    ) -> Result<PathBuf, Error> {
+        Self::check_name(file_name.as_ref())?;
         let dir = Self::default_directory().ok_or(Error::InvalidSessionDirectory)?;
-        let path = dir.join(format!("{}.{}", session.name, Self::DEFAULT_FILE_EXT));
-        let content = serde_yaml::to_string(&session)?;
+        let path = dir.join(format!("{}.{}", file_name.as_ref(), format.extension()));
+        let mut paths = Self::files(&dir, file_name.as_ref());
+        if let Some(existing) = paths.first().filter(|_| !force) {
+            return Err(Error::SessionExists(existing.clone()));
+        }
+        if paths.iter().any(|existing| *existing != path) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+            return Err(Error::DuplicateSession {
+                name: file_name.as_ref().to_string(),
+                paths,
+            });
+        }
+        let content = format.serialize(self)?;
 
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         fs::write(&path, content)?;
 
         Ok(path)
     }
 
-    pub fn list() -> Vec<String> {
-        let mut sessions: Vec<String> = Self::default_directory()
-            .and_then(|dir| fs::read_dir(dir).ok())
-            .into_iter()
-            .flatten()
-            .filter_map(|entry_result| entry_result.ok())
-            .map(|entry| entry.path())
-            .filter(|path| path.is_file())
-            .filter(|path| path.extension().is_some_and(|ext| ext == "yaml"))
-            .filter_map(|path| {
-                path.file_stem()
-                    .and_then(|stem| stem.to_str())
-                    .map(|s| s.to_string())
-            })
-            .collect();
-        sessions.sort();
+    /// Delete the file of session `name`, returning its path.
+    pub fn remove(name: &str) -> Result<PathBuf, Error> {
+        let path = Self::path(name)?;
+        fs::remove_file(&path)?;
+        Ok(path)
+    }
+
+    /// Rename session `from` to `to`, keeping its file in the same sessions
+    /// directory. The local session file belongs to its project and is not
+    /// moved: [`Session::copy`] it instead.
+    pub fn rename(from: &str, to: &str, force: bool) -> Result<PathBuf, Error> {
+        if from == Self::LOCAL_NAME {
+            return Err(Error::LocalSessionMove);
+        }
+        let source = Self::path(from)?;
+        let dir = source
+            .ancestors()
+            .nth(from.split('/').count())
+            .ok_or(Error::InvalidSessionDirectory)?
+            .to_path_buf();
+        let path = Self::copy_file(&source, &dir, to, force)?;
+        if path != source {
+            fs::remove_file(&source)?;
+        }
+        Ok(path)
+    }
+
+    /// Copy session `from` to a new session `to`, written to the first
+    /// sessions directory like the files of [`Session::create`].
+    pub fn copy(from: &str, to: &str, force: bool) -> Result<PathBuf, Error> {
+        let source = Self::path(from)?;
+        let dir = Self::default_directory().ok_or(Error::InvalidSessionDirectory)?;
+        Self::copy_file(&source, &dir, to, force)
+    }
+
+    // Write `source` as the file of session `to` in `dir`, in the same format
+    // and with `to` as its name. The files already defining `to` there are
+    // only replaced when `force` is set.
+    fn copy_file(source: &Path, dir: &Path, to: &str, force: bool) -> Result<PathBuf, Error> {
+        Self::check_name(to)?;
+        let format = Format::from_path(source).unwrap_or_default();
+        let extension = source
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or(format.extension());
+        let content = format.rename(&fs::read_to_string(source)?, to)?;
+
+        for existing in Self::files(dir, to) {
+            if !force {
+                return Err(Error::SessionExists(existing));
+            }
+            fs::remove_file(existing)?;
+        }
+        let path = dir.join(format!("{to}.{extension}"));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    // The session files under `dir`, named after their path from the sessions
+    // directory. Hidden subdirectories, e.g. `.git`, are skipped.
+    fn find_files(dir: &Path, namespace: &str, sessions: &mut Vec<SessionFile>) {
+        let entries = fs::read_dir(dir).into_iter().flatten();
+        for entry in entries.filter_map(|entry_result| entry_result.ok()) {
+            let path = entry.path();
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if is_dir && !file_name.starts_with('.') {
+                Self::find_files(&path, &format!("{namespace}{file_name}/"), sessions);
+            } else if path.is_file() && Format::from_path(&path).is_some() {
+                let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+                sessions.push(SessionFile {
+                    name: format!("{namespace}{stem}"),
+                    path,
+                    local: false,
+                    conflicts: vec![],
+                });
+            }
+        }
+    }
+
+    /// The sessions of the sessions directories, sorted by name, followed by
+    /// the local session, if any. A session in several directories is listed
+    /// once, from the first one, with the files defining it in other formats
+    /// there as its conflicts.
+    pub fn list() -> Vec<SessionFile> {
+        let mut sessions = vec![];
+        for dir in Self::directories() {
+            Self::find_files(&dir, "", &mut sessions);
+        }
+        // The sort is stable, so the first directory's file comes first.
+        sessions.sort_by(|a, b| a.name.cmp(&b.name));
+        sessions.dedup_by(|file, first| {
+            let duplicate = file.name == first.name;
+            if duplicate && file.path.parent() == first.path.parent() {
+                first.conflicts.push(file.path.clone());
+            }
+            duplicate
+        });
+        let local = env::current_dir().map(|cwd| Self::find_local(&cwd));
+        if let Ok(Ok(Some(path))) = local {
+            sessions.push(SessionFile {
+                name: Self::LOCAL_NAME.to_string(),
+                path,
+                local: true,
+                conflicts: vec![],
+            });
+        }
         sessions
     }
 }
@@ -161,13 +1105,47 @@ mod tests {
 
     #[test]
     fn load_session_invalid_dir() {
-        temp_env::with_vars_unset([Session::HOME_ENV, Session::DEFAULT_DIR_ENV], || {
+        temp_env::with_vars_unset(DIRECTORY_ENVS, || {
             let session = Session::load_from_name("a-session-path");
 
             assert!(matches!(session, Err(Error::InvalidSessionDirectory)));
         });
     }
 
+    #[test]
+    fn reject_unknown_window_and_pane_fields() {
+        let window = Session::load_from_string("{name: a, windows: [{nmae: editor}]}");
+        let pane = Session::load_from_string("{name: a, windows: [{panes: [{focsu: true}]}]}");
+
+        assert!(matches!(window, Err(Error::UnableToParseConfig(_))));
+        assert!(matches!(pane, Err(Error::UnableToParseConfig(_))));
+    }
+
+    #[test]
+    fn generate_json_schema() {
+        let schema = Session::schema();
+        let definitions = &schema.as_value()["$defs"];
+
+        assert_eq!(schema.as_value()["additionalProperties"], false);
+        assert_eq!(definitions["Window"]["additionalProperties"], false);
+        assert_eq!(definitions["Pane"]["additionalProperties"], false);
+        assert_eq!(
+            definitions["Pane"]["properties"]["focus"]["description"],
+            "Select this pane once the session is created."
+        );
+        assert_eq!(
+            schema.as_value()["properties"]["windows"]["default"],
+            serde_json::json!([{"panes": [{}]}])
+        );
+        assert_eq!(
+            schema.as_value()["properties"]["hooks"]["description"],
+            "Commands run as the session is created, attached, detached or stopped."
+        );
+        for field in ["focus", "exec", "remain_on_exit"] {
+            assert_eq!(definitions["Pane"]["properties"][field]["default"], false);
+        }
+    }
+
     #[test]
     fn session_must_have_one_window_with_one_pane() {
         let content = "name: simple-test";
@@ -197,55 +1175,729 @@ mod tests {
     }
 
     #[test]
-    fn list_all_sessions() {
+    fn read_window_layout() {
+        let content = "
+        name: simple-test
+        windows:
+          - layout: tiled
+          - layout: 5e4a,80x24,0,0{40x24,0,0,1,39x24,41,0,2}
+          -
+        ";
+        let session: Session = Session::load_from_string(content).unwrap();
+
+        assert_eq!(session.windows[0].layout, Some("tiled".to_string()));
+        assert_eq!(
+            session.windows[1].layout,
+            Some("5e4a,80x24,0,0{40x24,0,0,1,39x24,41,0,2}".to_string())
+        );
+        assert_eq!(session.windows[2].layout, None);
+    }
+
+    #[test]
+    fn read_pane_split_options() {
+        let content = "
+        name: simple-test
+        windows:
+          - panes:
+              -
+              - split: horizontal
+                size: 30%
+              - split: vertical
+                size: 20
+                target: 0
+        ";
+        let session: Session = Session::load_from_string(content).unwrap();
+        let panes = &session.windows[0].panes;
+
+        assert_eq!(panes[0].split, None);
+        assert_eq!(panes[0].size, None);
+        assert_eq!(panes[0].target, None);
+        assert_eq!(panes[1].split, Some(Split::Horizontal));
+        assert_eq!(panes[1].size, Some(Size::Percentage(30)));
+        assert_eq!(panes[2].split, Some(Split::Vertical));
+        assert_eq!(panes[2].size, Some(Size::Cells(20)));
+        assert_eq!(panes[2].target, Some(0));
+    }
+
+    #[test]
+    fn read_invalid_pane_size() {
+        for size in ["abc", "0%", "150%", "-3"] {
+            let content = format!(
+                "
+                name: simple-test
+                windows:
+                  - panes:
+                      - size: {size}
+                "
+            );
+            let session = Session::load_from_string(content);
+
+            assert!(matches!(session, Err(Error::UnableToParseConfig(_))));
+        }
+    }
+
+    #[test]
+    fn read_session_and_window_options() {
+        let content = "
+        name: simple-test
+        options:
+          mouse: on
+          history-limit: 5000
+        global_options:
+          escape-time: 0
+        windows:
+          - options:
+              synchronize-panes: true
+        ";
+        let session: Session = Session::load_from_string(content).unwrap();
+
+        assert_eq!(
+            session.options,
+            BTreeMap::from([
+                ("history-limit".to_string(), Scalar::Integer(5000)),
+                ("mouse".to_string(), Scalar::String("on".to_string())),
+            ])
+        );
+        assert_eq!(
+            session.global_options,
+            BTreeMap::from([("escape-time".to_string(), Scalar::Integer(0))])
+        );
+        assert_eq!(
+            session.windows[0].options,
+            BTreeMap::from([("synchronize-panes".to_string(), Scalar::Bool(true))])
+        );
+    }
+
+    #[test]
+    fn read_environment_variables() {
+        let content = "
+        name: simple-test
+        env:
+          RUST_LOG: info
+        windows:
+          - env:
+              PORT: 8080
+            panes:
+              - env:
+                  DEBUG: true
+        ";
+        let session: Session = Session::load_from_string(content).unwrap();
+
+        assert_eq!(
+            session.env,
+            BTreeMap::from([("RUST_LOG".to_string(), Scalar::String("info".to_string()))])
+        );
+        assert_eq!(
+            session.windows[0].env,
+            BTreeMap::from([("PORT".to_string(), Scalar::Integer(8080))])
+        );
+        assert_eq!(
+            session.windows[0].panes[0].env,
+            BTreeMap::from([("DEBUG".to_string(), Scalar::Bool(true))])
+        );
+    }
+
+    #[test]
+    fn read_hooks() {
+        let content = "
+        name: simple-test
+        hooks:
+          on_create: docker compose up -d
+          on_detach: docker compose stop
+        ";
+        let session: Session = Session::load_from_string(content).unwrap();
+
+        assert_eq!(
+            session.hooks,
+            Hooks {
+                on_create: Some("docker compose up -d".to_string()),
+                on_attach: None,
+                on_detach: Some("docker compose stop".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn read_pane_commands() {
+        let content = "
+        name: simple-test
+        pre:
+          - nvm use
+        windows:
+          - pre:
+              - source .venv/bin/activate
+            panes:
+              - command: make
+                commands:
+                  - make test
+                  - make lint
+        ";
+        let session: Session = Session::load_from_string(content).unwrap();
+
+        assert_eq!(session.pre, vec!["nvm use"]);
+        assert_eq!(session.windows[0].pre, vec!["source .venv/bin/activate"]);
+        assert_eq!(
+            session.windows[0].panes[0].commands().collect::<Vec<_>>(),
+            vec!["make", "make test", "make lint"]
+        );
+    }
+
+    #[test]
+    fn interpolate_variables() {
+        let content = "
+        name: '{{ project }}-{{ env }}'
+        vars:
+          project: api
+          env: dev
+          port: 8080
+        directory: ~/code/{{ project }}
+        env:
+          PORT: ${port:-3000}
+        windows:
+          - panes:
+              - command: cargo run -- --port {{ port }} --log ${TP_TEST_LOG}
+        ";
+        let session = Session::load_from_string(content).unwrap();
+        let variables = BTreeMap::from([("env".to_string(), "staging".to_string())]);
+
+        let session = temp_env::with_var("TP_TEST_LOG", Some("debug"), || {
+            session.interpolate(&variables).unwrap()
+        });
+
+        assert_eq!(session.name, "api-staging");
+        assert_eq!(session.directory, Some(PathBuf::from("~/code/api")));
+        assert_eq!(
+            session.env,
+            BTreeMap::from([("PORT".to_string(), Scalar::String("8080".to_string()))])
+        );
+        assert_eq!(
+            session.windows[0].panes[0].command,
+            Some("cargo run -- --port 8080 --log debug".to_string())
+        );
+    }
+
+    #[test]
+    fn interpolate_variables_from_env_maps() {
+        let content = "
+        name: db
+        env:
+          DATABASE_URL: postgres://localhost/${DB_NAME:-app}
+        windows:
+          - env:
+              DB_NAME: ignored
+            panes:
+              - command: psql ${DATABASE_URL}
+              - env:
+                  LOG_DIR: /var/log/${DB_NAME}
+                directory: ${LOG_DIR}
+                command: tail -f ${LOG_DIR}/${DB_NAME}.log
+          - panes:
+              - command: echo ${DB_NAME}
+        ";
+        let session = Session::load_from_string(content).unwrap();
+
+        let (result, session) = temp_env::with_var_unset("DB_NAME", || {
+            let first_window = Session {
+                windows: session.windows[..1].to_vec(),
+                ..session.clone()
+            };
+            (
+                session.interpolate(&BTreeMap::new()),
+                first_window.interpolate(&BTreeMap::new()).unwrap(),
+            )
+        });
+
+        assert!(matches!(&result, Err(Error::UndefinedVariables(names)) if *names == ["DB_NAME"]));
+        let panes = &session.windows[0].panes;
+        assert_eq!(
+            panes[0].command,
+            Some("psql postgres://localhost/app".to_string())
+        );
+        assert_eq!(panes[1].directory, Some(PathBuf::from("/var/log/ignored")));
+        assert_eq!(
+            panes[1].command,
+            Some("tail -f /var/log/ignored/ignored.log".to_string())
+        );
+    }
+
+    #[test]
+    fn report_undefined_variables() {
+        let session = Session::load_from_string(
+            "
+            name: '{{ project }}'
+            directory: ~/code/{{ project }}/{{ branch }}
+            ",
+        )
+        .unwrap();
+
+        let error = session.interpolate(&BTreeMap::new()).unwrap_err();
+
+        assert_eq!(error.to_string(), "undefined variables: branch, project");
+    }
+
+    const DIRECTORY_ENVS: [&str; 4] = [
+        Session::PATH_ENV,
+        Session::DEFAULT_DIR_ENV,
+        Session::CONFIG_HOME_ENV,
+        Session::HOME_ENV,
+    ];
+
+    fn with_sessions_dir<T>(dir: &Path, f: impl FnOnce() -> T) -> T {
+        temp_env::with_vars(
+            [
+                (Session::DEFAULT_DIR_ENV, Some(dir)),
+                (Session::PATH_ENV, None),
+            ],
+            f,
+        )
+    }
+
+    // The sessions directory sessions, whatever the directory tests run from.
+    fn list_names() -> Vec<String> {
+        Session::list()
+            .into_iter()
+            .filter(|session| !session.local)
+            .map(|session| session.name)
+            .collect()
+    }
+
+    fn with_session_files<T>(files: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
         let temp_test_dir = tempdir().expect("Failed to create temporary directory");
         let tmp_dir = temp_test_dir.path();
-        temp_env::with_var(
-            Session::DEFAULT_DIR_ENV,
-            Some(tmp_dir.to_str().unwrap()),
-            || {
-                fs::write(
-                    tmp_dir.join(format!("session1.{}", Session::DEFAULT_FILE_EXT)),
-                    "name: session1",
-                )
-                .unwrap();
-                fs::write(
-                    tmp_dir.join(format!("session2.{}", Session::DEFAULT_FILE_EXT)),
-                    "name: session2",
-                )
-                .unwrap();
-                fs::write(tmp_dir.join("other_file.txt"), "content").unwrap();
-                fs::create_dir(tmp_dir.join("subdir")).unwrap();
+        for (file_name, content) in files {
+            fs::write(tmp_dir.join(file_name), content).unwrap();
+        }
+        with_sessions_dir(tmp_dir, f)
+    }
 
-                let mut sessions = Session::list();
-                sessions.sort();
+    #[test]
+    fn extend_and_include_session_files() {
+        let base = "
+        name: base
+        directory: ~/code
+        env:
+          RUST_LOG: info
+          PORT: 3000
+        windows:
+          - name: editor
+            panes:
+              - command: nvim
+          - name: shell
+        ";
+        let git = "
+        name: git
+        windows:
+          - name: git
+            panes:
+              - command: lazygit
+        ";
+        let api = "
+        name: api
+        extends: base
+        include:
+          - git
+        env:
+          PORT: 8080
+        windows:
+          - name: editor
+            panes:
+              - command: hx
+          - name: server
+        ";
+
+        let files = [("base.yaml", base), ("git.yaml", git), ("api.yaml", api)];
+
+        let session = with_session_files(&files, || Session::load_from_name("api").unwrap());
 
-                assert_eq!(
-                    sessions,
-                    vec!["session1".to_string(), "session2".to_string()]
-                );
-            },
+        assert_eq!(session.name, "api");
+        assert_eq!(session.extends, None);
+        assert_eq!(session.directory, Some(PathBuf::from("~/code")));
+        assert_eq!(
+            session.env,
+            BTreeMap::from([
+                ("PORT".to_string(), Scalar::Integer(8080)),
+                ("RUST_LOG".to_string(), Scalar::String("info".to_string())),
+            ])
         );
+        assert_eq!(
+            session
+                .windows
+                .iter()
+                .map(|window| window.name.as_deref().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["editor", "shell", "server", "git"]
+        );
+        assert_eq!(session.windows[0].panes[0].command, Some("hx".to_string()));
+    }
+
+    #[test]
+    fn detect_inheritance_cycles() {
+        let files = [
+            ("a.yaml", "{name: a, extends: b}"),
+            ("b.yaml", "{name: b, include: [c]}"),
+            ("c.yaml", "{name: c, extends: a}"),
+        ];
+
+        let error = with_session_files(&files, || Session::load_from_name("a").unwrap_err());
+
+        assert_eq!(
+            error.to_string(),
+            "session files extend or include each other: a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn load_session_files_by_extension() {
+        let base = r#"
+        name = "base"
+        directory = "~/code"
+
+        [[windows]]
+        name = "editor"
+
+        [[windows.panes]]
+        command = "nvim"
+        "#;
+        let api = r#"{"name": "api", "extends": "base", "env": {"PORT": 8080}}"#;
+        let files = [
+            ("base.toml", base),
+            ("api.json", api),
+            ("web.yml", "name: web"),
+        ];
+
+        let (api, web, sessions) = with_session_files(&files, || {
+            (
+                Session::load_from_name("api").unwrap(),
+                Session::load_from_name("web").unwrap(),
+                list_names(),
+            )
+        });
+
+        assert_eq!(api.directory, Some(PathBuf::from("~/code")));
+        assert_eq!(
+            api.env,
+            BTreeMap::from([("PORT".to_string(), Scalar::Integer(8080))])
+        );
+        assert_eq!(api.windows[0].panes[0].command, Some("nvim".to_string()));
+        assert_eq!(web.name, "web");
+        assert_eq!(sessions, vec!["api", "base", "web"]);
+    }
+
+    #[test]
+    fn reject_duplicate_session_names_across_formats() {
+        let files = [("api.yaml", "name: api"), ("api.toml", "name = \"api\"")];
+
+        let (loaded, created, sessions) = with_session_files(&files, || {
+            (
+                Session::load_from_name("api"),
+                Session::create("api", Format::Json, true),
+                Session::list(),
+            )
+        });
+
+        assert!(matches!(loaded, Err(Error::DuplicateSession { paths, .. }) if paths.len() == 2));
+        assert!(matches!(created, Err(Error::DuplicateSession { paths, .. }) if paths.len() == 3));
+        let api = sessions.iter().find(|session| !session.local).unwrap();
+        assert_eq!(api.name, "api");
+        assert_eq!(api.conflicts.len(), 1);
+        assert_ne!(api.conflicts[0], api.path);
+        assert!(
+            matches!(api.conflict(), Some(Error::DuplicateSession { paths, .. }) if paths.len() == 2)
+        );
+    }
+
+    #[test]
+    fn create_session_in_each_format() {
+        for format in Format::ALL {
+            let session = with_session_files(&[], || {
+                let path = Session::create("api", format, false).unwrap();
+                assert_eq!(Format::from_path(&path), Some(format));
+                Session::load_from_name("api").unwrap()
+            });
+
+            assert_eq!(session.name, "api");
+            assert_eq!(session.windows[0].name, Some("shell".to_string()));
+        }
+    }
+
+    #[test]
+    fn refuse_to_overwrite_session_files() {
+        let files = [("api.yaml", "name: api # hand written")];
+
+        let (created, saved, forced, content) = with_session_files(&files, || {
+            let created = Session::create("api", Format::Yaml, false);
+            let saved = Session::load_from_name("api")
+                .unwrap()
+                .save("api", Format::Yaml, false);
+            let content = Session::path("api").map(fs::read_to_string);
+            let forced = Session::create("api", Format::Yaml, true);
+            (created, saved, forced, content)
+        });
+
+        assert!(matches!(created, Err(Error::SessionExists(path)) if path.ends_with("api.yaml")));
+        assert!(matches!(saved, Err(Error::SessionExists(path)) if path.ends_with("api.yaml")));
+        assert_eq!(content.unwrap().unwrap(), "name: api # hand written");
+        assert!(forced.is_ok());
+    }
+
+    #[test]
+    fn rename_sessions_in_place() {
+        let yaml = "# API\nname: api # the name\nwindows:\n  - name: api\n";
+        assert_eq!(
+            Format::Yaml.rename(yaml, "work/api").unwrap(),
+            "# API\nname: work/api # the name\nwindows:\n  - name: api\n"
+        );
+        assert_eq!(
+            Format::Yaml
+                .rename("windows: [{name: a}]\nname: 'it''s'\n", "8080")
+                .unwrap(),
+            "windows: [{name: a}]\nname: '8080'\n"
+        );
+
+        let json = "{\n  \"windows\": [{ \"name\": \"api\" }],\n  \"name\": \"a\\\"pi\"\n}\n";
+        assert_eq!(
+            Format::Json.rename(json, "web").unwrap(),
+            "{\n  \"windows\": [{ \"name\": \"api\" }],\n  \"name\": \"web\"\n}\n"
+        );
+
+        let toml = "# API\nname = \"api\"   # the name\n\n[[windows]]\nname = \"api\"\n";
+        assert_eq!(
+            Format::Toml.rename(toml, "web").unwrap(),
+            "# API\nname = \"web\"   # the name\n\n[[windows]]\nname = \"api\"\n"
+        );
+
+        let renamed = Format::Yaml.rename("extends: base\n", "web").unwrap();
+        assert_eq!(Format::Yaml.parse(&renamed).unwrap()["name"], "web");
+    }
+
+    #[test]
+    fn remove_rename_and_copy_sessions() {
+        let files = [
+            ("api.yaml", "name: api\n"),
+            ("web.toml", "name = \"web\"\n"),
+            ("db.json", "{\"name\": \"db\"}"),
+        ];
+
+        let (sessions, exists, local, renamed, copied) = with_session_files(&files, || {
+            let exists = Session::rename("api", "web", false);
+            let local = Session::rename(Session::LOCAL_NAME, "work/local", false);
+            let moved = Session::rename("db", "work/sql", false).unwrap();
+            let copied = Session::copy("api", "work/api", false).unwrap();
+            let forced = Session::rename("api", "web", true).unwrap();
+            Session::remove("work/api").unwrap();
+            assert!(Session::remove("work/api").is_err());
+
+            assert!(moved.ends_with("work/sql.json"));
+            assert!(copied.ends_with("work/api.yaml"));
+            assert!(forced.ends_with("web.yaml"));
+            let renamed = Session::load_from_name("work/sql").unwrap();
+            let copied = Session::load_from_name("web").unwrap();
+            (list_names(), exists, local, renamed.name, copied.name)
+        });
+
+        assert!(matches!(exists, Err(Error::SessionExists(path)) if path.ends_with("web.toml")));
+        assert!(matches!(local, Err(Error::LocalSessionMove)));
+        assert_eq!(sessions, vec!["web", "work/sql"]);
+        assert_eq!(renamed, "work/sql");
+        assert_eq!(copied, "web");
+    }
+
+    #[test]
+    fn find_local_session_files() {
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let root = temp_test_dir.path();
+        let nested = root.join("api/src");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(".tp.yaml"), "name: root").unwrap();
+
+        assert_eq!(
+            Session::find_local(&nested).unwrap(),
+            Some(root.join(".tp.yaml"))
+        );
+
+        fs::write(root.join("api/tp.toml"), "name = \"api\"").unwrap();
+        assert_eq!(
+            Session::find_local(&nested).unwrap(),
+            Some(root.join("api/tp.toml"))
+        );
+
+        fs::write(root.join("api/.tp.json"), "{\"name\": \"api\"}").unwrap();
+        assert!(matches!(
+            Session::find_local(&nested),
+            Err(Error::DuplicateSession { .. })
+        ));
+    }
+
+    #[test]
+    fn resolve_local_directories_from_the_session_file() {
+        let mut value: Value = serde_yaml::from_str(
+            "
+            name: api
+            windows:
+              - directory: web
+                panes:
+                  - directory: /srv
+                  - directory: ~/code
+                  - directory: '{{ root }}/src'
+            ",
+        )
+        .unwrap();
+
+        resolve_local_directory(&mut value, Path::new("/code/api"));
+        let session: Session = serde_yaml::from_value(value).unwrap();
+
+        assert_eq!(session.directory, Some(PathBuf::from("/code/api")));
+        assert_eq!(
+            session.windows[0].directory,
+            Some(PathBuf::from("/code/api/web"))
+        );
+        assert_eq!(
+            session.windows[0]
+                .panes
+                .iter()
+                .map(|pane| pane.directory.clone().unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("/srv"),
+                PathBuf::from("~/code"),
+                PathBuf::from("{{ root }}/src")
+            ]
+        );
+    }
+
+    #[test]
+    fn look_up_sessions_in_each_directory() {
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let root = temp_test_dir.path();
+        for (file_name, content) in [
+            ("personal/api.yaml", "name: personal"),
+            ("team/api.toml", "name = \"team\""),
+            ("team/web.yml", "name: web"),
+        ] {
+            fs::create_dir_all(root.join(file_name).parent().unwrap()).unwrap();
+            fs::write(root.join(file_name), content).unwrap();
+        }
+        let path = env::join_paths([root.join("personal"), root.join("team")]).unwrap();
+
+        let (api, sessions) = temp_env::with_vars(
+            [
+                (Session::PATH_ENV, Some(path.as_os_str())),
+                (Session::DEFAULT_DIR_ENV, Some(root.as_os_str())),
+            ],
+            || (Session::load_from_name("api").unwrap(), Session::list()),
+        );
+
+        assert_eq!(api.name, "personal");
+        assert_eq!(
+            sessions
+                .into_iter()
+                .filter(|session| !session.local)
+                .map(|session| session.path)
+                .collect::<Vec<_>>(),
+            vec![root.join("personal/api.yaml"), root.join("team/web.yml")]
+        );
+    }
+
+    #[test]
+    fn default_to_the_user_configuration_directory() {
+        let directories = |config_home: Option<&str>| {
+            temp_env::with_vars(
+                [
+                    (Session::PATH_ENV, None),
+                    (Session::DEFAULT_DIR_ENV, None),
+                    (Session::CONFIG_HOME_ENV, config_home),
+                    (Session::HOME_ENV, Some("/home/user")),
+                ],
+                Session::directories,
+            )
+        };
+
+        assert_eq!(
+            directories(Some("/home/user/.xdg")),
+            vec![PathBuf::from("/home/user/.xdg/tp")]
+        );
+        assert_eq!(
+            directories(Some("")),
+            vec![PathBuf::from("/home/user/.config/tp")]
+        );
+        assert_eq!(
+            directories(None),
+            vec![PathBuf::from("/home/user/.config/tp")]
+        );
+    }
+
+    #[test]
+    fn namespace_sessions_in_subdirectories() {
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let root = temp_test_dir.path();
+        fs::create_dir_all(root.join("work/clients")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("work/api.yaml"), "name: work/api").unwrap();
+        fs::write(root.join("work/clients/acme.json"), r#"{"name": "acme"}"#).unwrap();
+        fs::write(root.join(".git/config.yaml"), "name: config").unwrap();
+        fs::write(root.join("home.yaml"), "name: home").unwrap();
+
+        let (api, created, invalid, sessions) = with_sessions_dir(root, || {
+            (
+                Session::load_from_name("work/api").unwrap(),
+                Session::create("personal/blog", Format::Toml, false).unwrap(),
+                Session::load_from_name("work/../home"),
+                list_names(),
+            )
+        });
+
+        assert_eq!(api.name, "work/api");
+        assert_eq!(created, root.join("personal/blog.toml"));
+        assert!(matches!(invalid, Err(Error::InvalidSessionName(_))));
+        assert_eq!(
+            sessions,
+            vec!["home", "personal/blog", "work/api", "work/clients/acme"]
+        );
+    }
+
+    #[test]
+    fn list_all_sessions() {
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let tmp_dir = temp_test_dir.path();
+        with_sessions_dir(tmp_dir, || {
+            fs::write(
+                tmp_dir.join(format!("session1.{}", Format::Yaml.extension())),
+                "name: session1",
+            )
+            .unwrap();
+            fs::write(
+                tmp_dir.join(format!("session2.{}", Format::Yaml.extension())),
+                "name: session2",
+            )
+            .unwrap();
+            fs::write(tmp_dir.join("other_file.txt"), "content").unwrap();
+            fs::create_dir(tmp_dir.join("subdir")).unwrap();
+
+            let mut sessions = list_names();
+            sessions.sort();
+
+            assert_eq!(
+                sessions,
+                vec!["session1".to_string(), "session2".to_string()]
+            );
+        });
     }
 
     #[test]
     fn list_sessions_when_empty_dir() {
         let temp_test_dir = tempdir().expect("Failed to create temporary directory");
         let tmp_dir = temp_test_dir.path();
-        temp_env::with_var(
-            Session::DEFAULT_DIR_ENV,
-            Some(tmp_dir.to_str().unwrap()),
-            || {
-                let sessions = Session::list();
-                assert!(sessions.is_empty());
-            },
-        );
+        with_sessions_dir(tmp_dir, || {
+            let sessions = list_names();
+            assert!(sessions.is_empty());
+        });
     }
 
     #[test]
     fn list_sessions_when_sessions_dir_not_exists() {
-        temp_env::with_vars_unset([Session::HOME_ENV, Session::DEFAULT_DIR_ENV], || {
-            let sessions = Session::list();
+        temp_env::with_vars_unset(DIRECTORY_ENVS, || {
+            let sessions = list_names();
             assert!(sessions.is_empty());
         });
     }
@@ -255,16 +1907,12 @@ mod tests {
         let temp_file =
             tempfile::NamedTempFile::new().expect("Failed to create temporary directory");
         let tmp_dir = temp_file.path();
-        temp_env::with_var(
-            Session::DEFAULT_DIR_ENV,
-            Some(tmp_dir.to_str().unwrap()),
-            || {
-                fs::write(tmp_dir, "this is a file").unwrap();
+        with_sessions_dir(tmp_dir, || {
+            fs::write(tmp_dir, "this is a file").unwrap();
 
-                let sessions = Session::list();
-                assert!(sessions.is_empty());
-            },
-        );
+            let sessions = list_names();
+            assert!(sessions.is_empty());
+        });
     }
 
     #[test]
@@ -273,42 +1921,37 @@ mod tests {
         let temp_test_dir = tempdir().expect("Failed to create temporary directory");
         let tmp_dir = temp_test_dir.path();
 
-        temp_env::with_var(
-            Session::DEFAULT_DIR_ENV,
-            Some(tmp_dir.to_str().unwrap()),
-            || {
-                let result = Session::create(session_name);
-                assert!(result.is_ok());
+        with_sessions_dir(tmp_dir, || {
+            let result = Session::create(session_name, Format::Yaml, false);
+            assert!(result.is_ok());
 
-                let created_path = result.unwrap();
-                let expected_path =
-                    tmp_dir.join(format!("{}.{}", session_name, Session::DEFAULT_FILE_EXT));
-                assert_eq!(created_path, expected_path);
-                assert!(created_path.exists());
+            let created_path = result.unwrap();
+            let expected_path =
+                tmp_dir.join(format!("{}.{}", session_name, Format::Yaml.extension()));
+            assert_eq!(created_path, expected_path);
+            assert!(created_path.exists());
 
-                let content =
-                    fs::read_to_string(&created_path).expect("Failed to read created file");
-                let session: Session = Session::load_from_string(&content)
-                    .expect("Failed to deserialize created session");
+            let content = fs::read_to_string(&created_path).expect("Failed to read created file");
+            let session: Session =
+                Session::load_from_string(&content).expect("Failed to deserialize created session");
 
-                assert_eq!(session.name, session_name);
-                assert_eq!(session.directory, Some(".".into()));
-                assert_eq!(session.windows.len(), 1);
-                assert_eq!(session.windows[0].name, Some("shell".to_string()));
-                assert_eq!(session.windows[0].panes.len(), 1);
-                assert!(session.windows[0].panes[0].focus);
-                assert_eq!(
-                    session.windows[0].panes[0].command,
-                    Some("echo 'Hello :)'".to_string())
-                );
-            },
-        );
+            assert_eq!(session.name, session_name);
+            assert_eq!(session.directory, Some(".".into()));
+            assert_eq!(session.windows.len(), 1);
+            assert_eq!(session.windows[0].name, Some("shell".to_string()));
+            assert_eq!(session.windows[0].panes.len(), 1);
+            assert!(session.windows[0].panes[0].focus);
+            assert_eq!(
+                session.windows[0].panes[0].command,
+                Some("echo 'Hello :)'".to_string())
+            );
+        });
     }
 
     #[test]
     fn when_new_session_invalid_dir() {
-        temp_env::with_vars_unset([Session::HOME_ENV, Session::DEFAULT_DIR_ENV], || {
-            let result = Session::create("some-session");
+        temp_env::with_vars_unset(DIRECTORY_ENVS, || {
+            let result = Session::create("some-session", Format::Yaml, false);
             assert!(matches!(result, Err(Error::InvalidSessionDirectory)));
         });
     }
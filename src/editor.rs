@@ -0,0 +1,96 @@
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    path::Path,
+    process::{Command, ExitStatus},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("unable to run the editor `{editor}`: {reason}")]
+    UnableToRun { editor: String, reason: io::Error },
+    #[error("the editor `{editor}` exited with {status}")]
+    EditorFailed { editor: String, status: ExitStatus },
+    #[error("unable to read the answer: {0}")]
+    UnableToAsk(#[from] io::Error),
+}
+
+const DEFAULT_EDITOR: &str = "vi";
+
+/// The user's editor, from `$VISUAL`, then `$EDITOR`.
+pub fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Open `path` in the user's editor, waiting for it to exit.
+pub fn open(path: &Path) -> Result<(), Error> {
+    let editor = editor();
+    // Through the shell, so editors given with arguments like `code --wait`
+    // work as they do for git.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map_err(|reason| Error::UnableToRun {
+            editor: editor.clone(),
+            reason,
+        })?;
+
+    if !status.success() {
+        return Err(Error::EditorFailed { editor, status });
+    }
+    Ok(())
+}
+
+/// Ask a yes or no `question`, yes being the default. Without a terminal to
+/// answer from, the answer is no.
+pub fn confirm(question: &str) -> Result<bool, Error> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{question} [Y/n] ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    stdin.read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefer_visual_to_editor() {
+        let editor = |visual, editor| {
+            temp_env::with_vars([("VISUAL", visual), ("EDITOR", editor)], super::editor)
+        };
+
+        assert_eq!(editor(Some("nvim"), Some("nano")), "nvim");
+        assert_eq!(editor(Some(""), Some("nano")), "nano");
+        assert_eq!(editor(None, None), "vi");
+    }
+
+    #[test]
+    fn report_editor_failures() {
+        let path = Path::new("session.yaml");
+        let open = |editor| temp_env::with_vars([("VISUAL", Some(editor))], || open(path));
+
+        assert!(open("true").is_ok());
+        assert!(matches!(
+            open("false"),
+            Err(Error::EditorFailed { editor, .. }) if editor == "false"
+        ));
+    }
+}
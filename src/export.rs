@@ -0,0 +1,175 @@
+use crate::{
+    config::Session,
+    muxer::{Action, Arg, Error, Muxer, RecordingClient, SessionId, shell_quote},
+};
+use std::env;
+
+/// Render `session` as a POSIX shell script issuing the same tmux commands as
+/// [`Muxer::apply`], resolving `base-index` and `pane-base-index` when the
+/// script runs rather than when it is generated.
+pub fn to_shell_script(session: &Session) -> Result<String, Error> {
+    let existing = record(
+        session,
+        RecordingClient::default().with_session(SessionId::new(&session.name)),
+    )?;
+    let created = record(session, RecordingClient::default())?;
+
+    let mut lines = vec![
+        "#!/bin/sh".to_string(),
+        format!("# Generated by tp from session `{}`.", session.name),
+        "set -e".to_string(),
+        String::new(),
+        format!("session={}", shell_quote(&session.name)),
+        String::new(),
+        "if tmux has-session -t \"=$session\" 2>/dev/null; then".to_string(),
+    ];
+    lines.extend(commands(&existing).map(|command| format!("    {command}")));
+    lines.extend(["    exit 0".to_string(), "fi".to_string(), String::new()]);
+    lines.extend(commands(&created));
+
+    let mut script = lines.join("\n");
+    script.push('\n');
+    Ok(script)
+}
+
+fn record(session: &Session, client: RecordingClient) -> Result<Vec<Action>, Error> {
+    let mut runner = Muxer::new(client).with_inside_tmux(true);
+    runner.apply(session)?;
+    Ok(runner.into_client().actions().to_vec())
+}
+
+// The session check is the script's own `if`, option queries are stored in
+// shell variables named after the option, and whether to switch or attach is
+// decided when the script runs.
+fn commands(actions: &[Action]) -> impl Iterator<Item = String> {
+    actions
+        .iter()
+        .filter(|action| !matches!(action, Action::HasSession(_)))
+        .map(|action| match action {
+            Action::RunShell { directory, command } => {
+                format!("(cd {} && {command})", render_directory(directory))
+            }
+            Action::SwitchClient(session_id) => format!(
+                "if [ -n \"$TMUX\" ]; then {}; else {}; fi",
+                render(action),
+                render(&Action::AttachSession(session_id.clone()))
+            ),
+            Action::ShowOption(option_name) => format!(
+                "{}=$({})",
+                option_name.value().replace('-', "_"),
+                render(action)
+            ),
+            _ => render(action),
+        })
+}
+
+fn render(action: &Action) -> String {
+    let mut line = "tmux".to_string();
+    for arg in action.arguments() {
+        line.push(' ');
+        line.push_str(&render_arg(&arg));
+    }
+    line
+}
+
+// The recorded actions assume both base indexes are zero, so window and pane
+// indexes are offsets from the script's `base_index` and `pane_base_index`.
+fn render_arg(arg: &Arg) -> String {
+    match arg {
+        Arg::Text(text) => shell_quote(text),
+        Arg::Directory(directory) => render_directory(directory),
+        Arg::SessionName(_) => "\"$session\"".to_string(),
+        Arg::Session(_) => "\"=$session\"".to_string(),
+        Arg::SessionPane(_) => "\"=$session:\"".to_string(),
+        Arg::Window(window_id) => format!(
+            "\"=$session:{}\"",
+            offset("base_index", window_id.index().to_string())
+        ),
+        Arg::Pane(pane_id) => format!(
+            "\"=$session:{}.{}\"",
+            offset("base_index", pane_id.window().index().to_string()),
+            offset("pane_base_index", pane_id.index().to_string())
+        ),
+    }
+}
+
+fn offset(variable: &str, index: String) -> String {
+    match index.as_str() {
+        "0" => format!("${variable}"),
+        _ => format!("$(({variable} + {index}))"),
+    }
+}
+
+// Keep directories under the home directory portable across machines.
+fn render_directory(directory: &str) -> String {
+    let home = env::var("HOME").ok().filter(|home| !home.is_empty());
+    match home.and_then(|home| directory.strip_prefix(&home).map(str::to_owned)) {
+        Some(rest) if rest.is_empty() => "\"$HOME\"".to_string(),
+        Some(rest) if rest.starts_with('/') => format!("\"$HOME\"{}", shell_quote(&rest)),
+        _ => shell_quote(directory),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_session_as_shell_script() {
+        let session = Session::load_from_string(
+            "
+            name: my project
+            windows:
+              - name: editor
+                panes:
+                  - command: nvim .
+              - panes:
+                  -
+                  - split: horizontal
+                    focus: true
+            ",
+        )
+        .unwrap();
+
+        let script = to_shell_script(&session).unwrap();
+
+        assert_eq!(
+            script,
+            r#"#!/bin/sh
+# Generated by tp from session `my project`.
+set -e
+
+session='my project'
+
+if tmux has-session -t "=$session" 2>/dev/null; then
+    if [ -n "$TMUX" ]; then tmux switch-client -t "=$session"; else tmux attach-session -t "=$session"; fi
+    exit 0
+fi
+
+tmux new-session -d -c . -s "$session"
+base_index=$(tmux show-options -gv base-index)
+pane_base_index=$(tmux show-options -gv pane-base-index)
+tmux rename-window -t "=$session:$base_index" editor
+tmux send-keys -t "=$session:$base_index.$pane_base_index" 'nvim .' C-m
+tmux new-window -c . -t "=$session:$((base_index + 1))"
+tmux split-window -h -c . -t "=$session:$((base_index + 1)).$pane_base_index"
+tmux select-window -t "=$session:$((base_index + 1))"
+tmux select-pane -t "=$session:$((base_index + 1)).$((pane_base_index + 1))"
+if [ -n "$TMUX" ]; then tmux switch-client -t "=$session"; else tmux attach-session -t "=$session"; fi
+"#
+        );
+    }
+
+    #[test]
+    fn keep_home_directories_portable() {
+        temp_env::with_var("HOME", Some("/home/user"), || {
+            assert_eq!(render_directory("/home/user"), r#""$HOME""#);
+            assert_eq!(
+                render_directory("/home/user/my code"),
+                r#""$HOME"'/my code'"#
+            );
+            assert_eq!(render_directory("/home/username"), "/home/username");
+            assert_eq!(render_directory("/srv"), "/srv");
+        });
+    }
+}
@@ -1,2 +1,5 @@
 pub mod config;
+pub mod export;
 pub mod muxer;
+pub mod template;
+pub mod validate;
@@ -0,0 +1,249 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+use tp::{
+    config::{Session, SessionFile},
+    muxer::SessionInfo,
+};
+
+/// A session file and the state of its tmux session, as listed by
+/// `tp list --long` or `tp list --format json`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub file: SessionFile,
+    pub running: bool,
+    /// The running sessions loaded from the file, several for a templated
+    /// name loaded with different variables.
+    pub sessions: Vec<String>,
+    /// The number of clients attached to the running sessions.
+    pub clients: usize,
+    /// The windows of the running sessions, or of the session file otherwise.
+    pub windows: usize,
+    pub directory: Option<PathBuf>,
+    /// When `tp` last loaded one of the running sessions, in seconds since the
+    /// Unix epoch.
+    pub loaded_at: Option<u64>,
+}
+
+impl Entry {
+    pub fn new(file: SessionFile, running: &[SessionInfo]) -> Self {
+        let session = Session::load_from_name(&file.name)
+            .map(|session| session.interpolate(&BTreeMap::new()).unwrap_or(session))
+            .ok();
+        // Sessions loaded by `tp` record their file, as their name may come
+        // from template variables. Others can only be told by name.
+        let name = session.as_ref().map_or(&file.name, |session| &session.name);
+        let infos: Vec<_> = running
+            .iter()
+            .filter(|info| match &info.file {
+                Some(path) => *path == file.path,
+                None => info.name == *name,
+            })
+            .collect();
+        let windows = session.as_ref().map(|session| session.windows.len());
+        Self {
+            running: !infos.is_empty(),
+            sessions: infos.iter().map(|info| info.name.clone()).collect(),
+            clients: infos.iter().map(|info| info.attached).sum(),
+            windows: match infos.is_empty() {
+                true => windows.unwrap_or_default(),
+                false => infos.iter().map(|info| info.windows).sum(),
+            },
+            directory: session.and_then(|session| session.directory),
+            loaded_at: infos.iter().filter_map(|info| info.loaded_at).max(),
+            file,
+        }
+    }
+}
+
+/// Print the name of each session, and its path if `paths` is set.
+pub fn print_names<'a>(files: impl IntoIterator<Item = &'a SessionFile>, paths: bool) {
+    let rows = files
+        .into_iter()
+        .map(|file| match paths {
+            true => vec![display_name(file), file.path.display().to_string()],
+            false => vec![display_name(file)],
+        })
+        .collect();
+    print_table(rows);
+}
+
+/// Print `entries` as a table, `now` being the time their load time is shown
+/// relative to, in seconds since the Unix epoch.
+pub fn print_long(entries: &[Entry], paths: bool, now: u64) {
+    let mut header = vec![
+        "NAME",
+        "STATUS",
+        "CLIENTS",
+        "WINDOWS",
+        "DIRECTORY",
+        "LOADED",
+    ];
+    if paths {
+        header.push("PATH");
+    }
+    let mut rows = vec![header.into_iter().map(String::from).collect()];
+    rows.extend(entries.iter().map(|entry| long_row(entry, paths, now)));
+    print_table(rows);
+}
+
+fn long_row(entry: &Entry, paths: bool, now: u64) -> Vec<String> {
+    let missing = || "-".to_string();
+    let mut row = vec![
+        display_name(&entry.file),
+        match entry.running {
+            true => "running".to_string(),
+            false => "stopped".to_string(),
+        },
+        match entry.running {
+            true => entry.clients.to_string(),
+            false => missing(),
+        },
+        entry.windows.to_string(),
+        entry
+            .directory
+            .as_ref()
+            .map_or_else(missing, |directory| directory.display().to_string()),
+        entry
+            .loaded_at
+            .map_or_else(missing, |loaded_at| age(now.saturating_sub(loaded_at))),
+    ];
+    if paths {
+        row.push(entry.file.path.display().to_string());
+    }
+    row
+}
+
+fn display_name(file: &SessionFile) -> String {
+    match file.local {
+        true => format!("{} (local)", file.name),
+        false => file.name.clone(),
+    }
+}
+
+// How long ago something happened, in its largest whole unit.
+fn age(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s ago"),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+// Columns are padded to their widest cell, except the last one.
+fn print_table(rows: Vec<Vec<String>>) {
+    let mut widths = vec![];
+    for row in &rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows {
+        let last = row.len().saturating_sub(1);
+        let cells: Vec<_> = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| match i == last {
+                true => cell.clone(),
+                false => format!("{cell:width$}", width = widths[i]),
+            })
+            .collect();
+        println!("{}", cells.join("  "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+    use tempfile::tempdir;
+
+    #[test]
+    fn describe_running_and_stopped_sessions() {
+        let dir = tempdir().unwrap();
+        let api = "name: '{{ project }}'\nvars: { project: api }\ndirectory: ~/code/api\nwindows: [{}, {}]";
+        fs::write(dir.path().join("api.yaml"), api).unwrap();
+        fs::write(dir.path().join("web.yaml"), "name: web").unwrap();
+        let running = [SessionInfo {
+            name: "api".to_string(),
+            attached: 2,
+            windows: 3,
+            loaded_at: Some(1000),
+            file: None,
+        }];
+
+        let entries: Vec<_> = temp_env::with_vars(
+            [
+                ("TP_SESSIONS_DIR", Some(dir.path())),
+                ("TP_SESSIONS_PATH", None),
+            ],
+            || {
+                Session::list()
+                    .into_iter()
+                    .filter(|file| !file.local)
+                    .map(|file| Entry::new(file, &running))
+                    .collect()
+            },
+        );
+
+        let rows: Vec<_> = entries
+            .iter()
+            .map(|entry| long_row(entry, false, 4600))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["api", "running", "2", "3", "~/code/api", "1h ago"],
+                vec!["web", "stopped", "-", "1", "-", "-"],
+            ]
+        );
+    }
+
+    #[test]
+    fn match_sessions_loaded_from_templated_files() {
+        let dir = tempdir().unwrap();
+        let tpl = dir.path().join("tpl.yaml");
+        fs::write(&tpl, "name: '{{ project }}'\nwindows: [{}, {}]").unwrap();
+        fs::write(dir.path().join("web.yaml"), "name: web").unwrap();
+        let info = |name: &str, file: &Path| SessionInfo {
+            name: name.to_string(),
+            attached: 1,
+            windows: 2,
+            loaded_at: Some(1000),
+            file: Some(file.to_path_buf()),
+        };
+        // `web` is loaded from `tpl`, not from `web.yaml`.
+        let running = [info("web", &tpl), info("api", &tpl)];
+
+        let entries: Vec<_> = temp_env::with_vars(
+            [
+                ("TP_SESSIONS_DIR", Some(dir.path())),
+                ("TP_SESSIONS_PATH", None),
+            ],
+            || {
+                Session::list()
+                    .into_iter()
+                    .filter(|file| !file.local)
+                    .map(|file| Entry::new(file, &running))
+                    .collect()
+            },
+        );
+
+        assert_eq!(entries[0].file.name, "tpl");
+        assert!(entries[0].running);
+        assert_eq!(entries[0].sessions, ["web", "api"]);
+        assert_eq!((entries[0].clients, entries[0].windows), (2, 4));
+        assert_eq!(entries[1].file.name, "web");
+        assert!(!entries[1].running);
+    }
+
+    #[test]
+    fn show_ages_in_their_largest_unit() {
+        assert_eq!(age(59), "59s ago");
+        assert_eq!(age(60), "1m ago");
+        assert_eq!(age(7199), "1h ago");
+        assert_eq!(age(3 * 86400), "3d ago");
+    }
+}
@@ -1,44 +1,299 @@
 mod cli;
 mod completions;
+mod editor;
+mod list;
 mod tmux_client;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, ExportFormat, ListFormat};
 use completions::generate;
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tmux_client::TmuxClient;
-use tp::{config::Session, muxer::Muxer};
+use tp::{
+    config::{self, Format, Session, SessionFile},
+    export,
+    muxer::{self, Muxer, RecordingClient, SessionId},
+    validate::{Diagnostic, Severity},
+};
 
 fn main() -> Result<()> {
     match Cli::parse() {
-        Cli::List => {
-            for session in Session::list() {
-                println!("{session}");
+        Cli::List {
+            paths,
+            long,
+            running,
+            stopped,
+            format,
+        } => {
+            let files = Session::list();
+            for conflict in files.iter().filter_map(SessionFile::conflict) {
+                eprintln!("warning: {conflict}");
+            }
+            if !long && !running && !stopped && format == ListFormat::Text {
+                list::print_names(&files, paths);
+                return Ok(());
+            }
+
+            let client: TmuxClient = Default::default();
+            let sessions = Muxer::new(client).sessions()?;
+            let entries: Vec<_> = files
+                .into_iter()
+                .map(|file| list::Entry::new(file, &sessions))
+                .filter(|entry| !(running && !entry.running || stopped && entry.running))
+                .collect();
+            match format {
+                ListFormat::Json => println!("{}", serde_json::to_string_pretty(&entries)?),
+                ListFormat::Text if long => {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                    list::print_long(&entries, paths, now.as_secs());
+                }
+                ListFormat::Text => {
+                    list::print_names(entries.iter().map(|entry| &entry.file), paths)
+                }
             }
         }
-        Cli::New { session_name } => {
-            let session_path = Session::create(session_name)?;
+        Cli::New {
+            session_name,
+            format,
+            force,
+        } => {
+            let session_path = Session::create(session_name, format.into(), force)?;
             println!(
                 "Created new session configuration at: {}",
                 session_path.display()
             );
         }
-        Cli::Load { session } => {
+        Cli::Edit {
+            session,
+            new,
+            format,
+            vars,
+        } => {
+            let variables = vars.into_iter().collect();
+            let path = match Session::path(&session) {
+                Err(config::Error::UnableToLoad(e)) if new && e.kind() == ErrorKind::NotFound => {
+                    Session::create(&session, format.into(), false)?
+                }
+                path => path?,
+            };
+            loop {
+                editor::open(&path)?;
+                // Variables left undefined may be given when loading the session.
+                let diagnostics = Session::validate(&session, &variables, Severity::Warning);
+                if report(&diagnostics, false) == 0 {
+                    break;
+                }
+                if !editor::confirm("Reopen the session file?")? {
+                    bail!("session `{session}` has errors");
+                }
+            }
+        }
+        Cli::Rm { session } => {
+            let session_path = Session::remove(&session)?;
+            println!(
+                "Removed session configuration at: {}",
+                session_path.display()
+            );
+        }
+        Cli::Mv {
+            session,
+            new_name,
+            force,
+        } => {
+            let session_path = Session::rename(&session, &new_name, force)?;
+            println!("Moved session configuration to: {}", session_path.display());
+        }
+        Cli::Cp {
+            session,
+            new_name,
+            force,
+        } => {
+            let session_path = Session::copy(&session, &new_name, force)?;
+            println!(
+                "Copied session configuration to: {}",
+                session_path.display()
+            );
+        }
+        Cli::Load {
+            session,
+            rollback,
+            dry_run: true,
+            sync,
+            prune,
+            detach,
+            no_switch,
+            vars,
+        } => {
+            let session = load_session(&session, &vars.into_iter().collect())?;
+            // Only read from tmux, to plan against the running session if any.
+            let mut tmux: TmuxClient = Default::default();
+            let client = RecordingClient::mirror(&mut tmux, &SessionId::new(&session.name))?;
+            let mut runner = Muxer::new(client)
+                .with_rollback(rollback)
+                .with_sync(sync)
+                .with_prune(prune)
+                .with_attach(!detach)
+                .with_switch(!no_switch);
+            runner.apply(&session)?;
+            for action in runner.into_client().actions() {
+                println!("{action}");
+            }
+        }
+        Cli::Load {
+            session,
+            rollback,
+            sync,
+            prune,
+            detach,
+            no_switch,
+            vars,
+            ..
+        } => {
+            let session_file = Session::path(&session);
+            let session = load_session(&session, &vars.into_iter().collect())?;
             let client: TmuxClient = Default::default();
-            let mut runner = Muxer::new(client);
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            let mut runner = Muxer::new(client)
+                .with_rollback(rollback)
+                .with_sync(sync)
+                .with_prune(prune)
+                .with_attach(!detach)
+                .with_switch(!no_switch)
+                .with_loaded_at(now.as_secs())
+                .with_session_file(session_file?);
 
             let output = runner.apply(&session)?;
-            if output.is_new_session {
-                println!("Session {} was created!", output.session_name);
-            } else {
-                println!(
-                    "Session {} already exists! Switching...",
-                    output.session_name
-                );
+            let name = &output.session_name;
+            match (output.is_new_session, output.switched) {
+                (true, _) => println!("Session {name} was created!"),
+                (false, true) => println!("Session {name} already exists! Switching..."),
+                (false, false) => println!("Session {name} already exists!"),
+            }
+            for change in &output.changes {
+                println!("  {change}");
             }
         }
+        Cli::Validate {
+            session,
+            strict,
+            vars,
+            ..
+        } => {
+            // Every session file can't be given the variables it lacks.
+            let (names, undefined) = match session {
+                Some(session) => (vec![session], Severity::Error),
+                None => (
+                    Session::list()
+                        .into_iter()
+                        .map(|session| session.name)
+                        .collect(),
+                    Severity::Warning,
+                ),
+            };
+            let variables = vars.into_iter().collect();
+            let failures: usize = names
+                .iter()
+                .map(|name| report(&Session::validate(name, &variables, undefined), strict))
+                .sum();
+            match failures {
+                0 => {}
+                1 => bail!("session files have 1 problem"),
+                _ => bail!("session files have {failures} problems"),
+            }
+        }
+        Cli::Stop {
+            session,
+            all,
+            timeout,
+            vars,
+        } => {
+            let variables = vars.into_iter().collect();
+            let sessions = match session {
+                Some(session) => vec![session.interpolate(&variables)?],
+                // One broken session file shouldn't keep the others running.
+                None => Session::list()
+                    .iter()
+                    .filter_map(|file| {
+                        let session = Session::load_from_name(&file.name)
+                            .and_then(|session| session.interpolate(&variables));
+                        session
+                            .inspect_err(|e| {
+                                eprintln!("{}: warning: skipped: {e}", file.path.display())
+                            })
+                            .ok()
+                    })
+                    .collect(),
+            };
+
+            let client: TmuxClient = Default::default();
+            let mut runner = Muxer::new(client).with_stop_timeout(Duration::from_secs(timeout));
+            let mut failures = 0;
+            for session in sessions {
+                match runner.stop(&session) {
+                    Ok(()) => println!("Session {} was stopped!", session.name),
+                    Err(muxer::Error::SessionNotFound(_)) if all => {}
+                    Err(e) if all => {
+                        eprintln!("error: unable to stop session {}: {e}", session.name);
+                        failures += 1;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            match failures {
+                0 => {}
+                1 => bail!("1 session could not be stopped"),
+                _ => bail!("{failures} sessions could not be stopped"),
+            }
+        }
+        Cli::Freeze {
+            tmux_session,
+            name,
+            force,
+        } => {
+            let client: TmuxClient = Default::default();
+            let mut runner = Muxer::new(client);
+
+            let session = runner.capture(&tmux_session)?;
+            let session_path = session.save(name.unwrap_or(tmux_session), Format::Yaml, force)?;
+            println!("Saved session configuration at: {}", session_path.display());
+        }
+        Cli::Export {
+            format: ExportFormat::Sh,
+            session,
+            vars,
+        } => {
+            let session = session.interpolate(&vars.into_iter().collect())?;
+            print!("{}", export::to_shell_script(&session)?);
+        }
+        Cli::Schema => println!("{}", serde_json::to_string_pretty(&Session::schema())?),
         Cli::Completions { shell } => generate(shell)?,
     }
 
     Ok(())
 }
+
+/// Validate the session file `name` before loading it, printing what's wrong.
+fn load_session(name: &str, variables: &BTreeMap<String, String>) -> Result<Session> {
+    // Undefined variables are reported as errors here, so interpolating can't
+    // fail again with the same problem.
+    if report(&Session::validate(name, variables, Severity::Error), false) > 0 {
+        bail!("session `{name}` has errors");
+    }
+    Ok(Session::load_from_name(name)?.interpolate(variables)?)
+}
+
+/// Print `diagnostics`, returning how many should fail the command.
+fn report(diagnostics: &[Diagnostic], strict: bool) -> usize {
+    for diagnostic in diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    diagnostics
+        .iter()
+        .filter(|diagnostic| strict || diagnostic.severity == Severity::Error)
+        .count()
+}
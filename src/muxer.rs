@@ -1,10 +1,11 @@
-use crate::config::Session;
-#[cfg(test)]
-use mockall::automock;
+use crate::config::{self, Session};
 use std::{
+    collections::BTreeMap,
     env,
     fmt::Display,
     path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
@@ -37,21 +38,31 @@ impl Display for SessionId {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct WindowID(SessionId, Id);
+pub struct WindowID(SessionId, Id, Id);
 
 impl WindowID {
     pub fn new(session_id: &SessionId, window_id: impl Into<String>) -> Self {
-        let window_id = Id(format!("{}:{}", session_id, window_id.into()));
-        Self(session_id.clone(), window_id)
+        let index = Id(window_id.into());
+        let window_id = Id(format!("{}:{}", session_id, index));
+        Self(session_id.clone(), index, window_id)
+    }
+
+    pub fn session(&self) -> &SessionId {
+        &self.0
     }
 
     pub fn session_id(&self) -> &Id {
         self.0.id()
     }
 
-    pub fn id(&self) -> &Id {
+    /// The window index within its session.
+    pub fn index(&self) -> &Id {
         &self.1
     }
+
+    pub fn id(&self) -> &Id {
+        &self.2
+    }
 }
 
 impl Display for WindowID {
@@ -61,12 +72,17 @@ impl Display for WindowID {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct PaneID(WindowID, Id);
+pub struct PaneID(WindowID, Id, Id);
 
 impl PaneID {
     pub fn new(window_id: &WindowID, pane_id: impl Into<String>) -> Self {
-        let pane_id = Id(format!("{}.{}", window_id.clone(), pane_id.into()));
-        Self(window_id.clone(), pane_id)
+        let index = Id(pane_id.into());
+        let pane_id = Id(format!("{}.{}", window_id, index));
+        Self(window_id.clone(), index, pane_id)
+    }
+
+    pub fn window(&self) -> &WindowID {
+        &self.0
     }
 
     pub fn session_id(&self) -> &Id {
@@ -77,9 +93,14 @@ impl PaneID {
         self.0.id()
     }
 
-    pub fn id(&self) -> &Id {
+    /// The pane index within its window.
+    pub fn index(&self) -> &Id {
         &self.1
     }
+
+    pub fn id(&self) -> &Id {
+        &self.2
+    }
 }
 
 impl Display for PaneID {
@@ -127,9 +148,90 @@ impl OptionValue {
     }
 }
 
+impl From<&config::Scalar> for OptionValue {
+    fn from(value: &config::Scalar) -> Self {
+        match value {
+            config::Scalar::Bool(true) => Self::new("on"),
+            config::Scalar::Bool(false) => Self::new("off"),
+            value => Self::new(value.to_string()),
+        }
+    }
+}
+
+/// Where an option is set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionScope {
+    Global,
+    Session(SessionId),
+    Window(WindowID),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Layout(String);
 
+impl Layout {
+    pub fn new(layout: impl Into<String>) -> Self {
+        Self(layout.into())
+    }
+
+    pub fn value(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Horizontal,
+    Vertical,
+}
+
+impl From<config::Split> for Direction {
+    fn from(split: config::Split) -> Self {
+        match split {
+            config::Split::Horizontal => Self::Horizontal,
+            config::Split::Vertical => Self::Vertical,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Split {
+    pub direction: Option<Direction>,
+    pub size: Option<String>,
+}
+
+impl From<&config::Pane> for Split {
+    fn from(pane: &config::Pane) -> Self {
+        Self {
+            direction: pane.split.map(Direction::from),
+            size: pane.size.map(|size| size.to_string()),
+        }
+    }
+}
+
+/// A command started as a pane's process in place of the default shell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellCommand {
+    pub command: String,
+    pub remain_on_exit: bool,
+}
+
+/// Environment variables given to a new pane.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Environment(BTreeMap<String, String>);
+
+impl Environment {
+    pub fn new(variables: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self(variables.into_iter().collect())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Keys(String);
 
@@ -143,47 +245,976 @@ impl Keys {
     }
 }
 
-#[derive(Error, PartialEq, Debug)]
+/// A running session, as reported by `list-sessions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub name: String,
+    /// The number of clients attached to the session.
+    pub attached: usize,
+    pub windows: usize,
+    /// When `tp` last loaded the session, in seconds since the Unix epoch.
+    pub loaded_at: Option<u64>,
+    /// The session file `tp` last loaded the session from.
+    pub file: Option<PathBuf>,
+}
+
+impl SessionInfo {
+    /// The session options [`Muxer::apply`] records the load time and the
+    /// session file in.
+    const LOADED_AT_OPTION: &str = "@tp_loaded_at";
+    const FILE_OPTION: &str = "@tp_session_file";
+    const FORMAT: &str = "#{session_name}\t#{session_attached}\t#{session_windows}\t\
+                          #{@tp_loaded_at}\t#{@tp_session_file}";
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let info = Self {
+            name: fields.next()?.to_string(),
+            attached: fields.next()?.parse().ok()?,
+            windows: fields.next()?.parse().ok()?,
+            // Empty for sessions not loaded by `tp`, and trimmed from the
+            // last line of the output.
+            loaded_at: fields.next().and_then(|field| field.parse().ok()),
+            file: fields
+                .next()
+                .filter(|field| !field.is_empty())
+                .map(PathBuf::from),
+        };
+        fields.next().is_none().then_some(info)
+    }
+}
+
+/// A window of a running session, as reported by `list-windows`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub index: usize,
+    pub name: String,
+    pub layout: String,
+    pub active: bool,
+}
+
+impl WindowInfo {
+    const FORMAT: &str = "#{window_index}\t#{window_name}\t#{window_layout}\t#{window_active}";
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let info = Self {
+            index: fields.next()?.parse().ok()?,
+            name: fields.next()?.to_string(),
+            layout: fields.next()?.to_string(),
+            active: fields.next()? == "1",
+        };
+        fields.next().is_none().then_some(info)
+    }
+}
+
+/// A pane of a running window, as reported by `list-panes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaneInfo {
+    pub index: usize,
+    pub directory: String,
+    /// The name of the program running in the foreground.
+    pub command: String,
+    /// The full command line of that program, when known, as the pane start
+    /// command only covers panes created with a command.
+    pub command_line: String,
+    pub active: bool,
+    /// The process ID of the program the pane was started with.
+    pub pid: u32,
+}
+
+impl PaneInfo {
+    const FORMAT: &str = "#{pane_index}\t#{pane_current_path}\t#{pane_current_command}\t#{pane_active}\t#{pane_pid}\t#{pane_start_command}";
+
+    pub fn parse(line: &str) -> Option<Self> {
+        // The start command comes last as it may contain tabs, and may be
+        // trimmed away with the output when empty.
+        let mut fields = line.splitn(6, '\t');
+        let info = Self {
+            index: fields.next()?.parse().ok()?,
+            directory: fields.next()?.to_string(),
+            command: fields.next()?.to_string(),
+            active: fields.next()? == "1",
+            pid: fields.next()?.parse().ok()?,
+            command_line: fields.next().map(unquote).unwrap_or_default(),
+        };
+        Some(info)
+    }
+}
+
+// tmux double quotes start commands with spaces, escaping `"`, `$` and `\`.
+fn unquote(text: &str) -> String {
+    let Some(inner) = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    else {
+        return text.to_string();
+    };
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+#[derive(Error, Clone, PartialEq, Debug)]
 pub enum Error {
     #[error("unable to setup base ids: {0}")]
     BaseIdsError(String),
     #[error("option `{0}` not found")]
     OptionNotFound(String),
+    #[error(
+        "pane {pane} of window {window} can only target a pane created before it, got {target}"
+    )]
+    InvalidPaneTarget {
+        window: usize,
+        pane: usize,
+        target: usize,
+    },
+    #[error("unable to run `{command}`: {reason}")]
+    UnableToRunCommand { command: String, reason: String },
+    #[error("unexpected output from `{command}`: {output}")]
+    UnexpectedOutput { command: String, output: String },
+    #[error("session `{0}` not found")]
+    SessionNotFound(String),
+    #[error("`{command}` failed with {}{}", exit_code_to_string(.code), stderr_to_string(.stderr))]
+    CommandFailed {
+        command: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    #[error("`{hook}` hook failed: {error}")]
+    HookFailed { hook: String, error: Box<Error> },
+}
+
+fn exit_code_to_string(code: &Option<i32>) -> String {
+    code.map(|code| format!("exit code {code}"))
+        .unwrap_or_else(|| "no exit code".to_string())
+}
+
+fn stderr_to_string(stderr: &str) -> String {
+    match stderr {
+        "" => String::new(),
+        stderr => format!(": {stderr}"),
+    }
 }
 
-#[allow(dead_code)]
-#[cfg_attr(test, automock)]
 pub trait Client {
     fn get_option(&mut self, option_name: &OptionName) -> Result<OptionValue, Error>;
-    fn set_option(&mut self, option_name: &OptionName, option_value: &OptionValue);
+    fn set_option(
+        &mut self,
+        scope: &OptionScope,
+        option_name: &OptionName,
+        option_value: &OptionValue,
+    ) -> Result<(), Error>;
+
+    fn new_session(
+        &mut self,
+        session_id: &SessionId,
+        directory: &str,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error>;
+    fn set_environment(
+        &mut self,
+        session_id: &SessionId,
+        name: &str,
+        value: &str,
+    ) -> Result<(), Error>;
+    fn kill_session(&mut self, session_id: &SessionId) -> Result<(), Error>;
+    fn switch_to_session(&mut self, session_id: &SessionId) -> Result<(), Error>;
+    fn attach_session(&mut self, session_id: &SessionId) -> Result<(), Error>;
+    fn has_session(&mut self, session_id: &SessionId) -> Result<bool, Error>;
+    fn list_sessions(&mut self) -> Result<Vec<SessionInfo>, Error>;
+
+    fn list_windows(&mut self, session_id: &SessionId) -> Result<Vec<WindowInfo>, Error>;
+    fn new_window(
+        &mut self,
+        window_id: &WindowID,
+        directory: &str,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error>;
+    fn kill_window(&mut self, window_id: &WindowID) -> Result<(), Error>;
+    fn rename_window(
+        &mut self,
+        window_id: &WindowID,
+        window_name: &WindowName,
+    ) -> Result<(), Error>;
+
+    fn list_panes(&mut self, window_id: &WindowID) -> Result<Vec<PaneInfo>, Error>;
+    fn new_pane(
+        &mut self,
+        pane_id: &PaneID,
+        directory: &str,
+        split: &Split,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error>;
+    fn kill_pane(&mut self, pane_id: &PaneID) -> Result<(), Error>;
+    fn select_pane(&mut self, pane_id: &PaneID) -> Result<(), Error>;
+
+    fn send_keys(&mut self, pane_id: &PaneID, keys: Keys) -> Result<(), Error>;
+    fn interrupt(&mut self, pane_id: &PaneID) -> Result<(), Error>;
+
+    fn use_layout(&mut self, window_id: &WindowID, layout: &Layout) -> Result<(), Error>;
+
+    fn run_shell(&mut self, directory: &str, command: &str) -> Result<(), Error>;
+    fn set_hook(
+        &mut self,
+        session_id: &SessionId,
+        hook_name: &str,
+        command: &str,
+    ) -> Result<(), Error>;
+}
+
+/// A single command issued on behalf of a [`Client`] call, all of them tmux
+/// commands except [`Action::RunShell`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    ShowOption(OptionName),
+    SetOption {
+        scope: OptionScope,
+        option_name: OptionName,
+        option_value: OptionValue,
+    },
+    NewSession {
+        session_id: SessionId,
+        directory: String,
+        env: Environment,
+        command: Option<ShellCommand>,
+    },
+    SetEnvironment {
+        session_id: SessionId,
+        name: String,
+        value: String,
+    },
+    KillSession(SessionId),
+    SwitchClient(SessionId),
+    AttachSession(SessionId),
+    HasSession(SessionId),
+    ListSessions,
+    ListWindows(SessionId),
+    NewWindow {
+        window_id: WindowID,
+        directory: String,
+        env: Environment,
+        command: Option<ShellCommand>,
+    },
+    KillWindow(WindowID),
+    RenameWindow {
+        window_id: WindowID,
+        window_name: WindowName,
+    },
+    ListPanes(WindowID),
+    SplitWindow {
+        pane_id: PaneID,
+        directory: String,
+        split: Split,
+        env: Environment,
+        command: Option<ShellCommand>,
+    },
+    KillPane(PaneID),
+    SelectWindow(WindowID),
+    SelectPane(PaneID),
+    SendKeys {
+        pane_id: PaneID,
+        keys: Keys,
+    },
+    SendInterrupt(PaneID),
+    SelectLayout {
+        window_id: WindowID,
+        layout: Layout,
+    },
+    RunShell {
+        directory: String,
+        command: String,
+    },
+    SetHook {
+        session_id: SessionId,
+        hook_name: String,
+        command: String,
+    },
+}
+
+/// A tmux command-line argument, keeping targets and directories apart from
+/// plain text so they can be rendered differently.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Text(String),
+    Directory(String),
+    /// The name given to a new session.
+    SessionName(SessionId),
+    Session(SessionId),
+    /// The session of commands taking a pane target, like `set-option`.
+    SessionPane(SessionId),
+    Window(WindowID),
+    Pane(PaneID),
+}
+
+impl Display for Arg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(text) | Self::Directory(text) => write!(f, "{text}"),
+            Self::SessionName(session_id) => write!(f, "{session_id}"),
+            // Without `=`, tmux targets the first session the name is a
+            // prefix of, e.g. `api-v2` for `api`.
+            Self::Session(session_id) => write!(f, "={session_id}"),
+            // A pane target without `:` would be read as an exact window name.
+            Self::SessionPane(session_id) => write!(f, "={session_id}:"),
+            Self::Window(window_id) => write!(f, "={window_id}"),
+            Self::Pane(pane_id) => write!(f, "={pane_id}"),
+        }
+    }
+}
+
+impl From<&str> for Arg {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl Action {
+    /// The executable run for this action.
+    pub fn program(&self) -> &'static str {
+        match self {
+            Self::RunShell { .. } => "sh",
+            _ => "tmux",
+        }
+    }
+
+    /// The arguments passed to [`Action::program`].
+    pub fn args(&self) -> Vec<String> {
+        self.arguments().iter().map(Arg::to_string).collect()
+    }
+
+    pub fn arguments(&self) -> Vec<Arg> {
+        match self {
+            Self::ShowOption(option_name) => {
+                vec![
+                    "show-options".into(),
+                    "-gv".into(),
+                    option_name.value().into(),
+                ]
+            }
+            Self::SetOption {
+                scope,
+                option_name,
+                option_value,
+            } => {
+                let mut args = match scope {
+                    OptionScope::Global => vec!["set-option".into(), "-g".into()],
+                    OptionScope::Session(session_id) => vec![
+                        "set-option".into(),
+                        "-t".into(),
+                        Arg::SessionPane(session_id.clone()),
+                    ],
+                    OptionScope::Window(window_id) => vec![
+                        "set-window-option".into(),
+                        "-t".into(),
+                        Arg::Window(window_id.clone()),
+                    ],
+                };
+                args.extend([option_name.value().into(), option_value.value().into()]);
+                args
+            }
+            Self::NewSession {
+                session_id,
+                directory,
+                env,
+                command,
+            } => {
+                let mut args = vec![
+                    "new-session".into(),
+                    "-d".into(),
+                    "-c".into(),
+                    Arg::Directory(directory.clone()),
+                ];
+                args.extend(env_args(env));
+                args.extend(["-s".into(), Arg::SessionName(session_id.clone())]);
+                args.extend(command_args(command));
+                args
+            }
+            Self::SetEnvironment {
+                session_id,
+                name,
+                value,
+            } => vec![
+                "set-environment".into(),
+                "-t".into(),
+                Arg::Session(session_id.clone()),
+                name.as_str().into(),
+                value.as_str().into(),
+            ],
+            Self::KillSession(session_id) => vec![
+                "kill-session".into(),
+                "-t".into(),
+                Arg::Session(session_id.clone()),
+            ],
+            Self::SwitchClient(session_id) => vec![
+                "switch-client".into(),
+                "-t".into(),
+                Arg::Session(session_id.clone()),
+            ],
+            Self::AttachSession(session_id) => vec![
+                "attach-session".into(),
+                "-t".into(),
+                Arg::Session(session_id.clone()),
+            ],
+            Self::HasSession(session_id) => vec![
+                "has-session".into(),
+                "-t".into(),
+                Arg::Session(session_id.clone()),
+            ],
+            Self::ListSessions => vec![
+                "list-sessions".into(),
+                "-F".into(),
+                SessionInfo::FORMAT.into(),
+            ],
+            Self::ListWindows(session_id) => vec![
+                "list-windows".into(),
+                "-F".into(),
+                WindowInfo::FORMAT.into(),
+                "-t".into(),
+                Arg::Session(session_id.clone()),
+            ],
+            Self::NewWindow {
+                window_id,
+                directory,
+                env,
+                command,
+            } => {
+                let mut args = vec![
+                    "new-window".into(),
+                    "-c".into(),
+                    Arg::Directory(directory.clone()),
+                ];
+                args.extend(env_args(env));
+                args.extend(["-t".into(), Arg::Window(window_id.clone())]);
+                args.extend(command_args(command));
+                args
+            }
+            Self::KillWindow(window_id) => vec![
+                "kill-window".into(),
+                "-t".into(),
+                Arg::Window(window_id.clone()),
+            ],
+            Self::RenameWindow {
+                window_id,
+                window_name,
+            } => vec![
+                "rename-window".into(),
+                "-t".into(),
+                Arg::Window(window_id.clone()),
+                window_name.value().into(),
+            ],
+            Self::ListPanes(window_id) => vec![
+                "list-panes".into(),
+                "-F".into(),
+                PaneInfo::FORMAT.into(),
+                "-t".into(),
+                Arg::Window(window_id.clone()),
+            ],
+            Self::SplitWindow {
+                pane_id,
+                directory,
+                split,
+                env,
+                command,
+            } => {
+                let mut args = vec!["split-window".into()];
+                match split.direction {
+                    Some(Direction::Horizontal) => args.push("-h".into()),
+                    Some(Direction::Vertical) => args.push("-v".into()),
+                    None => {}
+                }
+                if let Some(size) = &split.size {
+                    args.extend(["-l".into(), size.as_str().into()]);
+                }
+                args.extend(["-c".into(), Arg::Directory(directory.clone())]);
+                args.extend(env_args(env));
+                args.extend(["-t".into(), Arg::Pane(pane_id.clone())]);
+                args.extend(command_args(command));
+                args
+            }
+            Self::KillPane(pane_id) => {
+                vec!["kill-pane".into(), "-t".into(), Arg::Pane(pane_id.clone())]
+            }
+            Self::SelectWindow(window_id) => vec![
+                "select-window".into(),
+                "-t".into(),
+                Arg::Window(window_id.clone()),
+            ],
+            Self::SelectPane(pane_id) => vec![
+                "select-pane".into(),
+                "-t".into(),
+                Arg::Pane(pane_id.clone()),
+            ],
+            Self::SendKeys { pane_id, keys } => vec![
+                "send-keys".into(),
+                "-t".into(),
+                Arg::Pane(pane_id.clone()),
+                keys.value().into(),
+                "C-m".into(),
+            ],
+            Self::SendInterrupt(pane_id) => vec![
+                "send-keys".into(),
+                "-t".into(),
+                Arg::Pane(pane_id.clone()),
+                "C-c".into(),
+            ],
+            Self::SelectLayout { window_id, layout } => vec![
+                "select-layout".into(),
+                "-t".into(),
+                Arg::Window(window_id.clone()),
+                layout.value().into(),
+            ],
+            Self::RunShell { command, .. } => vec!["-c".into(), command.as_str().into()],
+            Self::SetHook {
+                session_id,
+                hook_name,
+                command,
+            } => vec![
+                "set-hook".into(),
+                "-t".into(),
+                Arg::SessionPane(session_id.clone()),
+                hook_name.as_str().into(),
+                command.as_str().into(),
+            ],
+        }
+    }
+}
+
+// The running window each window of a session file is synced with, as an
+// index into `running`. Named windows are matched by name, so inserting a
+// window in the file doesn't shift the others, and unnamed ones by position.
+fn match_windows(windows: &[config::Window], running: &[WindowInfo]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; windows.len()];
+    for (wid, window) in windows.iter().enumerate() {
+        let Some(name) = &window.name else {
+            continue;
+        };
+        matches[wid] = running
+            .iter()
+            .enumerate()
+            .position(|(index, info)| info.name == *name && !matches.contains(&Some(index)));
+    }
+    for (wid, window) in windows.iter().enumerate() {
+        if window.name.is_none() && wid < running.len() && !matches.contains(&Some(wid)) {
+            matches[wid] = Some(wid);
+        }
+    }
+    matches
+}
+
+fn env_args(env: &Environment) -> impl Iterator<Item = Arg> {
+    env.iter()
+        .flat_map(|(name, value)| ["-e".into(), Arg::Text(format!("{name}={value}"))])
+}
+
+// `remain-on-exit` is set in the same tmux invocation, which targets the new
+// pane and runs before a short-lived command can exit.
+fn command_args(command: &Option<ShellCommand>) -> Vec<Arg> {
+    let Some(command) = command else {
+        return vec![];
+    };
+    let mut args = vec![command.command.as_str().into()];
+    if command.remain_on_exit {
+        args.extend([
+            ";".into(),
+            "set-option".into(),
+            "-p".into(),
+            "remain-on-exit".into(),
+            "on".into(),
+        ]);
+    }
+    args
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Self::RunShell { directory, command } = self {
+            return write!(f, "(cd {} && {command})", shell_quote(directory));
+        }
+        write!(f, "{}", self.program())?;
+        for arg in self.args() {
+            write!(f, " {}", shell_quote(&arg))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote `value` so a POSIX shell reads it back as a single word.
+pub fn shell_quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-+=%@:,./".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// A [`Client`] that records the tmux commands it is asked to run instead of
+/// running them, used to preview what [`Muxer::apply`] would do.
+pub struct RecordingClient {
+    actions: Vec<Action>,
+    sessions: Vec<SessionId>,
+    windows: Vec<(SessionId, WindowInfo, Vec<PaneInfo>)>,
+    options: Vec<(OptionName, OptionValue)>,
+    fail_on: Option<fn(&Action) -> bool>,
+}
+
+impl Default for RecordingClient {
+    fn default() -> Self {
+        Self {
+            actions: vec![],
+            sessions: vec![],
+            windows: vec![],
+            options: vec![
+                (OptionName::new("base-index"), OptionValue::new("0")),
+                (OptionName::new("pane-base-index"), OptionValue::new("0")),
+            ],
+            fail_on: None,
+        }
+    }
+}
+
+impl RecordingClient {
+    /// Pretend `session_id` and the base indexes are as `client` reports them,
+    /// so the actions recorded are the ones `client` would be asked to run.
+    pub fn mirror(client: &mut impl Client, session_id: &SessionId) -> Result<Self, Error> {
+        let mut recording = Self::default();
+        if !client.has_session(session_id)? {
+            return Ok(recording);
+        }
+        for name in ["base-index", "pane-base-index"] {
+            let name = OptionName::new(name);
+            let value = client.get_option(&name)?;
+            recording = recording.with_option(name, value);
+        }
+        recording = recording.with_session(session_id.clone());
+        for window in client.list_windows(session_id)? {
+            let panes = client.list_panes(&WindowID::new(session_id, window.index.to_string()))?;
+            recording = recording.with_window(session_id.clone(), window, panes);
+        }
+        Ok(recording)
+    }
+
+    /// Pretend `session_id` is already running.
+    pub fn with_session(mut self, session_id: SessionId) -> Self {
+        self.sessions.push(session_id);
+        self
+    }
+
+    /// Pretend `session_id` is running `window` with `panes`.
+    pub fn with_window(
+        mut self,
+        session_id: SessionId,
+        window: WindowInfo,
+        panes: Vec<PaneInfo>,
+    ) -> Self {
+        if !self.sessions.contains(&session_id) {
+            self.sessions.push(session_id.clone());
+        }
+        self.windows.push((session_id, window, panes));
+        self
+    }
+
+    /// Answer `option_name` queries with `option_value`.
+    pub fn with_option(mut self, option_name: OptionName, option_value: OptionValue) -> Self {
+        self.options.retain(|(name, _)| *name != option_name);
+        self.options.push((option_name, option_value));
+        self
+    }
+
+    /// Fail, without recording it, the first action matching `fail_on`.
+    pub fn with_failure(mut self, fail_on: fn(&Action) -> bool) -> Self {
+        self.fail_on = Some(fail_on);
+        self
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    fn record(&mut self, action: Action) -> Result<(), Error> {
+        if self.fail_on.is_some_and(|fail_on| fail_on(&action)) {
+            return Err(Error::CommandFailed {
+                command: action.to_string(),
+                code: Some(1),
+                stderr: "recorded failure".to_string(),
+            });
+        }
+        self.actions.push(action);
+        Ok(())
+    }
+}
+
+impl Client for RecordingClient {
+    fn get_option(&mut self, option_name: &OptionName) -> Result<OptionValue, Error> {
+        self.record(Action::ShowOption(option_name.clone()))?;
+        self.options
+            .iter()
+            .find(|(name, _)| name == option_name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| Error::OptionNotFound(option_name.value().to_string()))
+    }
+
+    fn set_option(
+        &mut self,
+        scope: &OptionScope,
+        option_name: &OptionName,
+        option_value: &OptionValue,
+    ) -> Result<(), Error> {
+        self.record(Action::SetOption {
+            scope: scope.clone(),
+            option_name: option_name.clone(),
+            option_value: option_value.clone(),
+        })
+    }
+
+    fn new_session(
+        &mut self,
+        session_id: &SessionId,
+        directory: &str,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error> {
+        self.record(Action::NewSession {
+            session_id: session_id.clone(),
+            directory: directory.to_string(),
+            env: env.clone(),
+            command: command.cloned(),
+        })
+    }
+
+    fn set_environment(
+        &mut self,
+        session_id: &SessionId,
+        name: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        self.record(Action::SetEnvironment {
+            session_id: session_id.clone(),
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    fn kill_session(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        self.record(Action::KillSession(session_id.clone()))
+    }
+
+    fn switch_to_session(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        self.record(Action::SwitchClient(session_id.clone()))
+    }
+
+    fn attach_session(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        self.record(Action::AttachSession(session_id.clone()))
+    }
+
+    fn has_session(&mut self, session_id: &SessionId) -> Result<bool, Error> {
+        self.record(Action::HasSession(session_id.clone()))?;
+        Ok(self.sessions.contains(session_id))
+    }
+
+    fn list_sessions(&mut self) -> Result<Vec<SessionInfo>, Error> {
+        self.record(Action::ListSessions)?;
+        Ok(self
+            .sessions
+            .iter()
+            .map(|session_id| SessionInfo {
+                name: session_id.id().to_string(),
+                attached: 0,
+                windows: self
+                    .windows
+                    .iter()
+                    .filter(|(id, _, _)| id == session_id)
+                    .count(),
+                loaded_at: None,
+                file: None,
+            })
+            .collect())
+    }
+
+    fn list_windows(&mut self, session_id: &SessionId) -> Result<Vec<WindowInfo>, Error> {
+        self.record(Action::ListWindows(session_id.clone()))?;
+        Ok(self
+            .windows
+            .iter()
+            .filter(|(id, _, _)| id == session_id)
+            .map(|(_, window, _)| window.clone())
+            .collect())
+    }
+
+    fn new_window(
+        &mut self,
+        window_id: &WindowID,
+        directory: &str,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error> {
+        self.record(Action::NewWindow {
+            window_id: window_id.clone(),
+            directory: directory.to_string(),
+            env: env.clone(),
+            command: command.cloned(),
+        })
+    }
+
+    fn kill_window(&mut self, window_id: &WindowID) -> Result<(), Error> {
+        self.record(Action::KillWindow(window_id.clone()))
+    }
+
+    fn rename_window(
+        &mut self,
+        window_id: &WindowID,
+        window_name: &WindowName,
+    ) -> Result<(), Error> {
+        self.record(Action::RenameWindow {
+            window_id: window_id.clone(),
+            window_name: window_name.clone(),
+        })
+    }
+
+    fn list_panes(&mut self, window_id: &WindowID) -> Result<Vec<PaneInfo>, Error> {
+        self.record(Action::ListPanes(window_id.clone()))?;
+        Ok(self
+            .windows
+            .iter()
+            .find(|(id, window, _)| {
+                id == window_id.session() && window.index.to_string() == window_id.index().0
+            })
+            .map(|(_, _, panes)| panes.clone())
+            .unwrap_or_default())
+    }
 
-    fn new_session(&mut self, session_id: &SessionId, directory: &str);
-    fn switch_to_session(&mut self, session_id: &SessionId);
-    fn has_session(&mut self, session_id: &SessionId) -> bool;
+    fn new_pane(
+        &mut self,
+        pane_id: &PaneID,
+        directory: &str,
+        split: &Split,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error> {
+        self.record(Action::SplitWindow {
+            pane_id: pane_id.clone(),
+            directory: directory.to_string(),
+            split: split.clone(),
+            env: env.clone(),
+            command: command.cloned(),
+        })
+    }
+
+    fn kill_pane(&mut self, pane_id: &PaneID) -> Result<(), Error> {
+        self.record(Action::KillPane(pane_id.clone()))
+    }
+
+    fn select_pane(&mut self, pane_id: &PaneID) -> Result<(), Error> {
+        self.record(Action::SelectWindow(pane_id.window().clone()))?;
+        self.record(Action::SelectPane(pane_id.clone()))
+    }
+
+    fn send_keys(&mut self, pane_id: &PaneID, keys: Keys) -> Result<(), Error> {
+        self.record(Action::SendKeys {
+            pane_id: pane_id.clone(),
+            keys,
+        })
+    }
+
+    fn interrupt(&mut self, pane_id: &PaneID) -> Result<(), Error> {
+        self.record(Action::SendInterrupt(pane_id.clone()))
+    }
+
+    fn use_layout(&mut self, window_id: &WindowID, layout: &Layout) -> Result<(), Error> {
+        self.record(Action::SelectLayout {
+            window_id: window_id.clone(),
+            layout: layout.clone(),
+        })
+    }
 
-    fn new_window(&mut self, session_id: &SessionId, directory: &str);
-    fn rename_window(&mut self, window_id: &WindowID, window_name: &WindowName);
+    fn run_shell(&mut self, directory: &str, command: &str) -> Result<(), Error> {
+        self.record(Action::RunShell {
+            directory: directory.to_string(),
+            command: command.to_string(),
+        })
+    }
 
-    fn new_pane(&mut self, window_id: &WindowID, directory: &str);
-    fn select_pane(&mut self, pane_id: &PaneID);
+    fn set_hook(
+        &mut self,
+        session_id: &SessionId,
+        hook_name: &str,
+        command: &str,
+    ) -> Result<(), Error> {
+        self.record(Action::SetHook {
+            session_id: session_id.clone(),
+            hook_name: hook_name.to_string(),
+            command: command.to_string(),
+        })
+    }
+}
 
-    fn send_keys(&mut self, pane_id: &PaneID, keys: Keys);
+/// A difference found while syncing a running session with its file, using
+/// tmux window and pane indexes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    NewWindow(usize),
+    NewPane(usize, usize),
+    ExtraWindow(usize),
+    ExtraPane(usize, usize),
+    KilledWindow(usize),
+    KilledPane(usize, usize),
+}
 
-    fn use_layout(&mut self, layout: &Layout);
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NewWindow(window) => write!(f, "created window {window}"),
+            Self::NewPane(window, pane) => write!(f, "created pane {window}.{pane}"),
+            Self::ExtraWindow(window) => write!(f, "window {window} is not in the session file"),
+            Self::ExtraPane(window, pane) => {
+                write!(f, "pane {window}.{pane} is not in the session file")
+            }
+            Self::KilledWindow(window) => write!(f, "killed window {window}"),
+            Self::KilledPane(window, pane) => write!(f, "killed pane {window}.{pane}"),
+        }
+    }
 }
 
+/// Every window index with the indexes of its panes.
+pub type WindowIndexes = Vec<(usize, Vec<usize>)>;
+
 pub struct Output {
     pub session_name: String,
     pub is_new_session: bool,
-    pub windows: Vec<(usize, Vec<usize>)>,
+    /// Whether the tmux client `apply` ran from was switched to the session.
+    /// Attaching to it replaces the process instead, so `apply` never returns.
+    pub switched: bool,
+    pub windows: WindowIndexes,
+    pub changes: Vec<Change>,
 }
 
 pub struct Muxer<C: Client> {
     client: C,
     base_window_id: usize,
     base_pane_id: usize,
+    rollback: bool,
+    sync: bool,
+    prune: bool,
+    stop_timeout: Duration,
+    inside_tmux: bool,
+    attach: bool,
+    switch: bool,
+    loaded_at: Option<u64>,
+    session_file: Option<PathBuf>,
 }
 
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 fn directory_to_string(directory: Option<PathBuf>) -> String {
     directory
         .map(expand_tilde)
@@ -191,7 +1222,7 @@ fn directory_to_string(directory: Option<PathBuf>) -> String {
         .unwrap_or_else(|| ".".to_owned())
 }
 
-fn expand_tilde(path: impl AsRef<Path>) -> PathBuf {
+pub(crate) fn expand_tilde(path: impl AsRef<Path>) -> PathBuf {
     let path = path.as_ref();
     path.strip_prefix("~/")
         .ok()
@@ -203,6 +1234,65 @@ fn expand_tilde(path: impl AsRef<Path>) -> PathBuf {
         .unwrap_or_else(|| path.to_owned())
 }
 
+fn collapse_tilde(directory: &str) -> PathBuf {
+    let path = Path::new(directory);
+    env::var("HOME")
+        .ok()
+        .and_then(|home| path.strip_prefix(home).ok())
+        .map(|suffix| match suffix.as_os_str().is_empty() {
+            true => PathBuf::from("~"),
+            false => Path::new("~").join(suffix),
+        })
+        .unwrap_or_else(|| path.to_owned())
+}
+
+const SHELLS: &[&str] = &[
+    "bash", "dash", "elvish", "fish", "ksh", "nu", "sh", "tcsh", "xonsh", "zsh",
+];
+
+fn is_shell(command: &str) -> bool {
+    SHELLS.contains(&command.trim_start_matches('-'))
+}
+
+/// A tmux command running `command` from `directory` once no client is left
+/// attached to the session.
+fn detach_hook(directory: &str, command: &str) -> String {
+    // `run-shell` expands formats, so a literal `#` has to be doubled.
+    let script = format!(
+        "cd {} && {}",
+        shell_quote(directory),
+        command.replace('#', "##")
+    );
+    let run_shell = format!("run-shell -b {}", shell_quote(&script));
+    format!(
+        "if-shell -F '#{{==:#{{session_attached}},0}}' {}",
+        shell_quote(&run_shell)
+    )
+}
+
+fn pane_commands<'a>(
+    session: &'a Session,
+    window: &'a config::Window,
+    pane: &'a config::Pane,
+) -> impl Iterator<Item = &'a String> {
+    session.pre.iter().chain(&window.pre).chain(pane.commands())
+}
+
+/// The process an `exec` pane starts with: all its commands, in order.
+fn shell_command(
+    session: &Session,
+    window: &config::Window,
+    pane: &config::Pane,
+) -> Option<ShellCommand> {
+    let commands: Vec<&str> = pane_commands(session, window, pane)
+        .map(String::as_str)
+        .collect();
+    (pane.exec && !commands.is_empty()).then(|| ShellCommand {
+        command: commands.join(" && "),
+        remain_on_exit: pane.remain_on_exit,
+    })
+}
+
 fn resolve_directory(
     session_dir: &Option<PathBuf>,
     window_dir: &Option<PathBuf>,
@@ -215,97 +1305,563 @@ fn resolve_directory(
         .map(|p| p.to_owned())
 }
 
+/// Merge environments from the outermost to the innermost, so inner values win.
+fn resolve_environment<'a>(
+    envs: impl IntoIterator<Item = &'a BTreeMap<String, config::Scalar>>,
+) -> Environment {
+    Environment::new(
+        envs.into_iter()
+            .flatten()
+            .map(|(name, value)| (name.clone(), value.to_string())),
+    )
+}
+
+fn pane_position(order: &[usize], pid: usize) -> usize {
+    order
+        .iter()
+        .position(|&p| p == pid)
+        .expect("pane must be created before it is referenced")
+}
+
+fn validate_pane_targets(session: &Session) -> Result<(), Error> {
+    for (wid, window) in session.windows.iter().enumerate() {
+        for (pid, pane) in window.panes.iter().enumerate() {
+            if let Some(target) = pane.target.filter(|&target| target >= pid) {
+                return Err(Error::InvalidPaneTarget {
+                    window: wid,
+                    pane: pid,
+                    target,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
 impl<C: Client> Muxer<C> {
     pub fn new(client: C) -> Self {
         Self {
             client,
             base_window_id: 0,
             base_pane_id: 0,
+            rollback: false,
+            sync: false,
+            prune: false,
+            stop_timeout: Duration::from_secs(5),
+            inside_tmux: env::var_os("TMUX").is_some_and(|tmux| !tmux.is_empty()),
+            attach: true,
+            switch: true,
+            loaded_at: None,
+            session_file: None,
         }
     }
 
-    pub fn apply(&mut self, session: &Session) -> Result<Output, Error> {
-        let session_id = SessionId::new(&session.name);
-        let mut windows = vec![];
-        if self.client.has_session(&session_id) {
-            self.client.switch_to_session(&session_id);
-            return Ok(Output {
-                session_name: session.name.clone(),
-                is_new_session: false,
-                windows,
-            });
-        }
+    /// Kill the partially created session when `apply` fails halfway.
+    pub fn with_rollback(mut self, rollback: bool) -> Self {
+        self.rollback = rollback;
+        self
+    }
 
-        self.setup_base_ids()?;
+    /// Create the windows and panes missing from an already running session.
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
 
-        let first_window = session.windows.first();
-        let initial_dir = resolve_directory(
+    /// Kill the windows and panes of a synced session missing from its file.
+    pub fn with_prune(mut self, prune: bool) -> Self {
+        self.prune = prune;
+        self
+    }
+
+    /// How long `stop` waits for interrupted commands before killing the session.
+    pub fn with_stop_timeout(mut self, stop_timeout: Duration) -> Self {
+        self.stop_timeout = stop_timeout;
+        self
+    }
+
+    /// Whether `apply` runs from a tmux client, detected from `$TMUX` by default.
+    pub fn with_inside_tmux(mut self, inside_tmux: bool) -> Self {
+        self.inside_tmux = inside_tmux;
+        self
+    }
+
+    /// Attach to the session when `apply` runs outside tmux.
+    pub fn with_attach(mut self, attach: bool) -> Self {
+        self.attach = attach;
+        self
+    }
+
+    /// Switch the current client to the session when `apply` runs inside tmux.
+    pub fn with_switch(mut self, switch: bool) -> Self {
+        self.switch = switch;
+        self
+    }
+
+    /// Record on the session that `apply` loaded it at `loaded_at`, in seconds
+    /// since the Unix epoch, as reported by [`Muxer::sessions`].
+    pub fn with_loaded_at(mut self, loaded_at: u64) -> Self {
+        self.loaded_at = Some(loaded_at);
+        self
+    }
+
+    /// Record on the session that `apply` loaded it from `session_file`, which
+    /// templated session names don't tell, as reported by [`Muxer::sessions`].
+    pub fn with_session_file(mut self, session_file: PathBuf) -> Self {
+        self.session_file = Some(session_file);
+        self
+    }
+
+    pub fn into_client(self) -> C {
+        self.client
+    }
+
+    pub fn apply(&mut self, session: &Session) -> Result<Output, Error> {
+        let session_id = SessionId::new(&session.name);
+        if self.client.has_session(&session_id)? {
+            let (windows, changes) = if self.sync {
+                validate_pane_targets(session)?;
+                self.setup_base_ids()?;
+                self.sync_windows(session, &session_id)?
+            } else {
+                (vec![], vec![])
+            };
+            if self.focuses_session() {
+                self.run_hook("on_attach", &session.hooks.on_attach, session)?;
+            }
+            self.record_load(&session_id)?;
+            self.focus_session(&session_id)?;
+            return Ok(Output {
+                session_name: session.name.clone(),
+                is_new_session: false,
+                switched: self.switches_client(),
+                windows,
+                changes,
+            });
+        }
+
+        validate_pane_targets(session)?;
+        self.run_hook("on_create", &session.hooks.on_create, session)?;
+
+        let first_window = session.windows.first();
+        let initial_dir = resolve_directory(
             &session.directory,
             &first_window.and_then(|window| window.directory.clone()),
             &first_window
                 .and_then(|window| window.panes.first().and_then(|pane| pane.directory.clone())),
         );
         let initial_dir = directory_to_string(initial_dir);
-        self.client.new_session(&session_id, &initial_dir);
+        let initial_env = resolve_environment(
+            [&session.env]
+                .into_iter()
+                .chain(first_window.map(|window| &window.env))
+                .chain(first_window.and_then(|window| window.panes.first().map(|pane| &pane.env))),
+        );
+        let initial_command = first_window.and_then(|window| {
+            window
+                .panes
+                .first()
+                .and_then(|pane| shell_command(session, window, pane))
+        });
+        self.client.new_session(
+            &session_id,
+            &initial_dir,
+            &initial_env,
+            initial_command.as_ref(),
+        )?;
+
+        let windows = match self.build_session(session, &session_id) {
+            Ok(windows) => windows,
+            Err(e) => {
+                if self.rollback {
+                    // The build error is more useful than a failed cleanup.
+                    let _ = self.client.kill_session(&session_id);
+                }
+                return Err(e);
+            }
+        };
 
-        let session_dir = session.directory.clone();
+        self.record_load(&session_id)?;
+        self.focus_session(&session_id)?;
+
+        Ok(Output {
+            session_name: session.name.clone(),
+            is_new_session: true,
+            switched: self.switches_client(),
+            windows,
+            changes: vec![],
+        })
+    }
+
+    fn build_session(
+        &mut self,
+        session: &Session,
+        session_id: &SessionId,
+    ) -> Result<WindowIndexes, Error> {
+        // Read once the session exists, so the server has loaded its config.
+        self.setup_base_ids()?;
+        for (name, value) in &session.env {
+            self.client
+                .set_environment(session_id, name, &value.to_string())?;
+        }
+        self.set_options(&OptionScope::Global, &session.global_options)?;
+        self.set_options(&OptionScope::Session(session_id.clone()), &session.options)?;
+        if let Some(command) = &session.hooks.on_detach {
+            let directory = directory_to_string(session.directory.clone());
+            self.client.set_hook(
+                session_id,
+                "client-detached",
+                &detach_hook(&directory, command),
+            )?;
+        }
+
+        let mut windows = vec![];
         let mut focus_pane: Option<PaneID> = None;
         for (wid, window) in session.windows.iter().enumerate() {
-            let window_dir = resolve_directory(&session_dir, &window.directory, &None);
+            let widx = self.base_window_id + wid;
+            let window_id = WindowID::new(session_id, widx.to_string());
             if wid > 0 {
-                let initial_dir = resolve_directory(
-                    &session_dir,
-                    &window_dir,
-                    &window.panes.first().and_then(|pane| pane.directory.clone()),
-                );
-                self.client
-                    .new_window(&session_id, &directory_to_string(initial_dir));
+                self.create_window(session, window, &window_id)?;
             }
 
-            let widx = self.base_window_id + wid;
-            let window_id = WindowID::new(&session_id, widx.to_string());
-            if let Some(window_name) = &window.name {
+            let panes = self.build_window(session, window, &window_id, 0)?;
+            if let Some(pid) = window.panes.iter().rposition(|pane| pane.focus) {
+                focus_pane = Some(PaneID::new(&window_id, panes[pid].to_string()));
+            }
+
+            windows.push((widx, panes));
+        }
+
+        if let Some(pane) = focus_pane {
+            self.client.select_pane(&pane)?;
+        }
+
+        Ok(windows)
+    }
+
+    fn focuses_session(&self) -> bool {
+        match self.inside_tmux {
+            true => self.switch,
+            false => self.attach,
+        }
+    }
+
+    fn switches_client(&self) -> bool {
+        self.inside_tmux && self.switch
+    }
+
+    // Recorded before focusing, as attaching replaces the current process.
+    fn record_load(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        let scope = OptionScope::Session(session_id.clone());
+        let loaded_at = self.loaded_at.map(|loaded_at| loaded_at.to_string());
+        let session_file = self
+            .session_file
+            .as_ref()
+            .map(|file| file.to_string_lossy().into_owned());
+        let options = [
+            (SessionInfo::LOADED_AT_OPTION, loaded_at),
+            (SessionInfo::FILE_OPTION, session_file),
+        ];
+        for (name, value) in options {
+            if let Some(value) = value {
                 self.client
-                    .rename_window(&window_id, &WindowName::new(window_name));
+                    .set_option(&scope, &OptionName::new(name), &OptionValue::new(value))?;
             }
+        }
+        Ok(())
+    }
 
-            let mut panes: Vec<usize> = vec![];
-            for (pid, pane) in window.panes.iter().enumerate() {
-                let pidx = self.base_pane_id + pid;
-                let pane_id = PaneID::new(&window_id, pidx.to_string());
-                if pane.focus {
-                    focus_pane = Some(pane_id.clone());
+    // Attaching replaces the current process, so it has to come last.
+    fn focus_session(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        match (self.focuses_session(), self.inside_tmux) {
+            (false, _) => Ok(()),
+            (true, true) => self.client.switch_to_session(session_id),
+            (true, false) => self.client.attach_session(session_id),
+        }
+    }
+
+    fn run_hook(
+        &mut self,
+        hook: &str,
+        command: &Option<String>,
+        session: &Session,
+    ) -> Result<(), Error> {
+        let Some(command) = command else {
+            return Ok(());
+        };
+        let directory = directory_to_string(session.directory.clone());
+        self.client
+            .run_shell(&directory, command)
+            .map_err(|e| Error::HookFailed {
+                hook: hook.to_string(),
+                error: Box::new(e),
+            })
+    }
+
+    // Windows are matched to the running ones by `match_windows`, and panes
+    // by position within their window.
+    fn sync_windows(
+        &mut self,
+        session: &Session,
+        session_id: &SessionId,
+    ) -> Result<(WindowIndexes, Vec<Change>), Error> {
+        let running = self.client.list_windows(session_id)?;
+        let next_index = running
+            .last()
+            .map_or(self.base_window_id, |window| window.index + 1);
+
+        let matches = match_windows(&session.windows, &running);
+
+        let mut windows = vec![];
+        let mut changes = vec![];
+        // New windows are appended after the running ones.
+        let mut new_indexes = next_index..;
+        for (window, matched) in session.windows.iter().zip(&matches) {
+            let Some(info) = matched.map(|index| &running[index]) else {
+                let widx = new_indexes.next().unwrap_or(next_index);
+                let window_id = WindowID::new(session_id, widx.to_string());
+                self.create_window(session, window, &window_id)?;
+                let panes = self.build_window(session, window, &window_id, 0)?;
+                changes.push(Change::NewWindow(widx));
+                windows.push((widx, panes));
+                continue;
+            };
+
+            let window_id = WindowID::new(session_id, info.index.to_string());
+            let running_panes = self.client.list_panes(&window_id)?;
+            let existing = running_panes.len().min(window.panes.len());
+            let panes = self.build_window(session, window, &window_id, existing)?;
+            changes.extend(
+                panes[existing..]
+                    .iter()
+                    .map(|&pidx| Change::NewPane(info.index, pidx)),
+            );
+
+            // Kill from the last pane so the remaining indexes don't shift.
+            for pane in running_panes.iter().skip(window.panes.len()).rev() {
+                if self.prune {
+                    let pane_id = PaneID::new(&window_id, pane.index.to_string());
+                    self.client.kill_pane(&pane_id)?;
+                    changes.push(Change::KilledPane(info.index, pane.index));
+                } else {
+                    changes.push(Change::ExtraPane(info.index, pane.index));
                 }
+            }
+
+            windows.push((info.index, panes));
+        }
+
+        let extra = running
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !matches.contains(&Some(*index)));
+        for (_, info) in extra {
+            if self.prune {
+                let window_id = WindowID::new(session_id, info.index.to_string());
+                self.client.kill_window(&window_id)?;
+                changes.push(Change::KilledWindow(info.index));
+            } else {
+                changes.push(Change::ExtraWindow(info.index));
+            }
+        }
+
+        Ok((windows, changes))
+    }
+
+    fn create_window(
+        &mut self,
+        session: &Session,
+        window: &config::Window,
+        window_id: &WindowID,
+    ) -> Result<(), Error> {
+        let first_pane = window.panes.first();
+        let initial_dir = resolve_directory(
+            &session.directory,
+            &window.directory,
+            &first_pane.and_then(|pane| pane.directory.clone()),
+        );
+        let initial_env = resolve_environment(
+            [&session.env, &window.env]
+                .into_iter()
+                .chain(first_pane.map(|pane| &pane.env)),
+        );
+        let initial_command = first_pane.and_then(|pane| shell_command(session, window, pane));
+        self.client.new_window(
+            window_id,
+            &directory_to_string(initial_dir),
+            &initial_env,
+            initial_command.as_ref(),
+        )
+    }
+
+    /// Set up the panes of `window` after its first `existing` ones, returning
+    /// the tmux index of every configured pane.
+    fn build_window(
+        &mut self,
+        session: &Session,
+        window: &config::Window,
+        window_id: &WindowID,
+        existing: usize,
+    ) -> Result<Vec<usize>, Error> {
+        if existing == 0 {
+            if let Some(window_name) = &window.name {
+                self.client
+                    .rename_window(window_id, &WindowName::new(window_name))?;
+            }
+            self.set_options(&OptionScope::Window(window_id.clone()), &window.options)?;
+        }
 
+        let session_dir = session.directory.clone();
+        let window_dir = resolve_directory(&session_dir, &window.directory, &None);
+
+        // tmux numbers panes by their position in the window, so splitting
+        // an earlier pane shifts the index of every pane after it.
+        let mut order: Vec<usize> = (0..existing).collect();
+        for (pid, pane) in window.panes.iter().enumerate().skip(existing) {
+            let mut position = 0;
+            if pid > 0 {
+                let target = pane.target.unwrap_or(pid - 1);
+                let target_position = pane_position(&order, target);
+                let target_id =
+                    PaneID::new(window_id, (self.base_pane_id + target_position).to_string());
                 let pane_dir = resolve_directory(&session_dir, &window_dir, &pane.directory);
-                if pid > 0 {
-                    self.client
-                        .new_pane(&window_id, &directory_to_string(pane_dir));
-                }
+                let pane_env = resolve_environment([&session.env, &window.env, &pane.env]);
+                self.client.new_pane(
+                    &target_id,
+                    &directory_to_string(pane_dir),
+                    &Split::from(pane),
+                    &pane_env,
+                    shell_command(session, window, pane).as_ref(),
+                )?;
+                position = target_position + 1;
+            }
+            order.insert(position, pid);
 
-                if let Some(cmd) = &pane.command {
-                    self.client.send_keys(&pane_id, Keys::new(cmd));
+            if !pane.exec {
+                let pane_id = PaneID::new(window_id, (self.base_pane_id + position).to_string());
+                for command in pane_commands(session, window, pane) {
+                    self.client.send_keys(&pane_id, Keys::new(command))?;
                 }
-
-                panes.push(pidx);
             }
+        }
 
-            windows.push((widx, panes));
+        if let Some(layout) = window
+            .layout
+            .as_ref()
+            .filter(|_| existing < window.panes.len())
+        {
+            self.client.use_layout(window_id, &Layout::new(layout))?;
         }
 
-        if let Some(pane) = focus_pane {
-            self.client.select_pane(&pane);
+        Ok((0..window.panes.len())
+            .map(|pid| self.base_pane_id + pane_position(&order, pid))
+            .collect())
+    }
+
+    fn set_options(
+        &mut self,
+        scope: &OptionScope,
+        options: &BTreeMap<String, config::Scalar>,
+    ) -> Result<(), Error> {
+        for (name, value) in options {
+            self.client
+                .set_option(scope, &OptionName::new(name), &OptionValue::from(value))?;
         }
+        Ok(())
+    }
 
-        self.client.switch_to_session(&session_id);
+    /// Describe the running session `session_name` as a session file.
+    pub fn capture(&mut self, session_name: &str) -> Result<Session, Error> {
+        let session_id = SessionId::new(session_name);
+        if !self.client.has_session(&session_id)? {
+            return Err(Error::SessionNotFound(session_name.to_string()));
+        }
 
-        Ok(Output {
-            session_name: session.name.clone(),
-            is_new_session: true,
+        let mut windows = vec![];
+        for window in self.client.list_windows(&session_id)? {
+            let window_id = WindowID::new(&session_id, window.index.to_string());
+            let panes = self.client.list_panes(&window_id)?;
+            windows.push((window, panes));
+        }
+
+        let session_dir = windows
+            .first()
+            .and_then(|(_, panes)| panes.first())
+            .map(|pane| collapse_tilde(&pane.directory));
+        let windows = windows
+            .into_iter()
+            .map(|(window, panes)| config::Window {
+                name: Some(window.name),
+                layout: (panes.len() > 1).then_some(window.layout),
+                panes: panes
+                    .into_iter()
+                    .map(|pane| config::Pane {
+                        focus: window.active && pane.active,
+                        directory: Some(collapse_tilde(&pane.directory))
+                            .filter(|dir| Some(dir) != session_dir.as_ref()),
+                        command: (!is_shell(&pane.command)).then_some(
+                            match pane.command_line.is_empty() {
+                                true => pane.command,
+                                false => pane.command_line,
+                            },
+                        ),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Session {
+            name: session_name.to_string(),
+            directory: session_dir,
             windows,
+            ..Default::default()
         })
     }
 
+    /// The running tmux sessions, whether loaded by `tp` or not.
+    pub fn sessions(&mut self) -> Result<Vec<SessionInfo>, Error> {
+        self.client.list_sessions()
+    }
+
+    /// Interrupt the commands running in `session`, then kill it once they
+    /// have exited or the stop timeout is over.
+    pub fn stop(&mut self, session: &Session) -> Result<(), Error> {
+        let session_id = SessionId::new(&session.name);
+        if !self.client.has_session(&session_id)? {
+            return Err(Error::SessionNotFound(session.name.clone()));
+        }
+
+        self.run_hook("on_stop", &session.hooks.on_stop, session)?;
+
+        let mut busy_panes = self.busy_panes(&session_id)?;
+        for pane_id in &busy_panes {
+            self.client.interrupt(pane_id)?;
+        }
+        let deadline = Instant::now() + self.stop_timeout;
+        while !busy_panes.is_empty() && Instant::now() < deadline {
+            thread::sleep(STOP_POLL_INTERVAL);
+            busy_panes = self.busy_panes(&session_id)?;
+        }
+
+        self.client.kill_session(&session_id)
+    }
+
+    // Panes running anything but their shell.
+    fn busy_panes(&mut self, session_id: &SessionId) -> Result<Vec<PaneID>, Error> {
+        let mut busy_panes = vec![];
+        for window in self.client.list_windows(session_id)? {
+            let window_id = WindowID::new(session_id, window.index.to_string());
+            for pane in self.client.list_panes(&window_id)? {
+                if !is_shell(&pane.command) {
+                    busy_panes.push(PaneID::new(&window_id, pane.index.to_string()));
+                }
+            }
+        }
+        Ok(busy_panes)
+    }
+
     fn setup_base_ids(&mut self) -> Result<(), Error> {
         self.base_window_id = self.get_index("base-index")?;
         self.base_pane_id = self.get_index("pane-base-index")?;
@@ -328,52 +1884,880 @@ impl<C: Client> Muxer<C> {
 mod tests {
     use super::*;
 
-    fn make_mock_client() -> MockClient {
-        let mut mock_client = MockClient::new();
-        mock_client.expect_has_session().return_const(false);
-        mock_client.expect_new_session().return_const(());
-        mock_client.expect_switch_to_session().return_const(());
-        mock_client
-            .expect_get_option()
-            .returning(|_| Ok(OptionValue::new("0")));
-        mock_client.expect_send_keys().return_const(());
-        mock_client
+    fn apply(client: RecordingClient, content: &str) -> (Result<Output, Error>, Vec<String>) {
+        apply_with(Muxer::new(client).with_inside_tmux(true), content)
+    }
+
+    fn apply_with(
+        mut runner: Muxer<RecordingClient>,
+        content: &str,
+    ) -> (Result<Output, Error>, Vec<String>) {
+        let session: Session = Session::load_from_string(content).unwrap();
+        let output = runner.apply(&session);
+        let actions = runner
+            .into_client()
+            .actions()
+            .iter()
+            .map(|action| action.to_string())
+            .collect();
+        (output, actions)
     }
 
     #[test]
     fn switch_to_session_if_exists() {
-        let session: Session = Session::load_from_string("name: test").unwrap();
-        let mut mock_client = MockClient::new();
-        mock_client.expect_has_session().return_const(true);
-        mock_client.expect_switch_to_session().return_const(());
-        let mut runner = Muxer::new(mock_client);
+        let client = RecordingClient::default().with_session(SessionId::new("test"));
 
-        let output = runner.apply(&session).unwrap();
+        let (output, actions) = apply(client, "name: test");
+        let output = output.unwrap();
 
         assert_eq!(output.session_name, "test".to_string());
         assert!(!output.is_new_session);
+        assert!(output.switched);
+        assert_eq!(
+            actions,
+            vec!["tmux has-session -t =test", "tmux switch-client -t =test"]
+        );
     }
 
     #[test]
-    fn create_a_session_if_not_exists() {
-        let session: Session = Session::load_from_string("name: test").unwrap();
-        let mock_client = make_mock_client();
-        let mut runner = Muxer::new(mock_client);
+    fn attach_to_session_outside_tmux() {
+        let runner = Muxer::new(RecordingClient::default()).with_inside_tmux(false);
 
-        let output = runner.apply(&session).unwrap();
+        let (output, actions) = apply_with(runner, "name: test");
+
+        assert!(output.is_ok());
+        assert_eq!(actions.last().unwrap(), "tmux attach-session -t =test");
+    }
+
+    #[test]
+    fn record_when_the_session_is_loaded() {
+        let runner = Muxer::new(RecordingClient::default())
+            .with_inside_tmux(false)
+            .with_loaded_at(1760000000)
+            .with_session_file(PathBuf::from("/tp/test.yaml"));
+
+        let (output, actions) = apply_with(runner, "name: test");
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions[actions.len() - 3..],
+            [
+                "tmux set-option -t =test: @tp_loaded_at 1760000000",
+                "tmux set-option -t =test: @tp_session_file /tp/test.yaml",
+                "tmux attach-session -t =test",
+            ]
+        );
+
+        let client = RecordingClient::default().with_session(SessionId::new("test"));
+        let runner = Muxer::new(client)
+            .with_inside_tmux(true)
+            .with_loaded_at(1760000000);
+
+        let (output, actions) = apply_with(runner, "name: test");
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions,
+            [
+                "tmux has-session -t =test",
+                "tmux set-option -t =test: @tp_loaded_at 1760000000",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn leave_the_client_alone_when_asked() {
+        let content = "
+            name: test
+            hooks:
+              on_attach: git fetch
+            ";
+        let client = RecordingClient::default().with_session(SessionId::new("test"));
+        let runner = Muxer::new(client)
+            .with_inside_tmux(false)
+            .with_attach(false);
+
+        let (output, actions) = apply_with(runner, content);
+
+        assert!(output.is_ok());
+        assert_eq!(actions, ["tmux has-session -t =test"]);
+
+        let client = RecordingClient::default().with_session(SessionId::new("test"));
+        let runner = Muxer::new(client).with_inside_tmux(true).with_switch(false);
+
+        let (output, actions) = apply_with(runner, content);
+
+        assert!(!output.unwrap().switched);
+        assert_eq!(actions, ["tmux has-session -t =test"]);
+    }
+
+    #[test]
+    fn create_a_session_if_not_exists() {
+        let (output, actions) = apply(RecordingClient::default(), "name: test");
+        let output = output.unwrap();
 
         assert_eq!(output.session_name, "test".to_string());
         assert!(output.is_new_session);
+        assert_eq!(
+            actions,
+            vec![
+                "tmux has-session -t =test",
+                "tmux new-session -d -c . -s test",
+                "tmux show-options -gv base-index",
+                "tmux show-options -gv pane-base-index",
+                "tmux switch-client -t =test",
+            ]
+        );
     }
 
     #[test]
     fn base_ids_starts_at_zero() {
-        let session: Session = Session::load_from_string("name: test").unwrap();
-        let mock_client = make_mock_client();
-        let mut runner = Muxer::new(mock_client);
+        let (output, _) = apply(RecordingClient::default(), "name: test");
+
+        assert_eq!(output.unwrap().windows, vec![(0, vec![0])]);
+    }
+
+    #[test]
+    fn base_ids_follow_tmux_options() {
+        let client = RecordingClient::default()
+            .with_option(OptionName::new("base-index"), OptionValue::new("1"))
+            .with_option(OptionName::new("pane-base-index"), OptionValue::new("1"));
+
+        let (output, actions) = apply(
+            client,
+            "
+            name: test
+            windows:
+              - panes:
+                  - focus: true
+            ",
+        );
+
+        assert_eq!(output.unwrap().windows, vec![(1, vec![1])]);
+        assert!(actions.contains(&"tmux select-pane -t =test:1.1".to_string()));
+    }
+
+    #[test]
+    fn apply_layout_after_panes_are_created() {
+        let (output, actions) = apply(
+            RecordingClient::default(),
+            "
+            name: test
+            windows:
+              - layout: main-vertical
+                panes:
+                  -
+                  -
+            ",
+        );
+
+        assert_eq!(output.unwrap().windows, vec![(0, vec![0, 1])]);
+        assert_eq!(
+            actions[4..],
+            [
+                "tmux split-window -c . -t =test:0.0",
+                "tmux select-layout -t =test:0 main-vertical",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_panes_from_their_target() {
+        let (output, actions) = apply(
+            RecordingClient::default(),
+            "
+            name: test
+            windows:
+              - panes:
+                  -
+                  - split: horizontal
+                    size: 30%
+                    command: tail -f log
+                  - split: vertical
+                    target: 0
+                    command: cargo watch
+                    focus: true
+            ",
+        );
+
+        assert_eq!(output.unwrap().windows, vec![(0, vec![0, 2, 1])]);
+        assert_eq!(
+            actions[4..],
+            [
+                "tmux split-window -h -l 30% -c . -t =test:0.0",
+                "tmux send-keys -t =test:0.1 'tail -f log' C-m",
+                "tmux split-window -v -c . -t =test:0.0",
+                "tmux send-keys -t =test:0.1 'cargo watch' C-m",
+                "tmux select-window -t =test:0",
+                "tmux select-pane -t =test:0.1",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_pane_targeting_a_later_pane() {
+        let (output, _) = apply(
+            RecordingClient::default(),
+            "
+            name: test
+            windows:
+              - panes:
+                  -
+                  - target: 1
+            ",
+        );
+
+        assert!(matches!(
+            output,
+            Err(Error::InvalidPaneTarget {
+                window: 0,
+                pane: 1,
+                target: 1
+            })
+        ));
+    }
+
+    const FAILING_SPLIT: &str = "
+        name: test
+        windows:
+          - panes:
+              -
+              - command: never sent
+        ";
+
+    fn is_split(action: &Action) -> bool {
+        matches!(action, Action::SplitWindow { .. })
+    }
+
+    #[test]
+    fn stop_at_the_first_failure() {
+        let client = RecordingClient::default().with_failure(is_split);
+
+        let (output, actions) = apply(client, FAILING_SPLIT);
+
+        assert!(matches!(output, Err(Error::CommandFailed { .. })));
+        assert_eq!(
+            actions.last().unwrap(),
+            "tmux show-options -gv pane-base-index"
+        );
+    }
+
+    #[test]
+    fn rollback_partial_session_on_failure() {
+        let session: Session = Session::load_from_string(FAILING_SPLIT).unwrap();
+        let client = RecordingClient::default().with_failure(is_split);
+        let mut runner = Muxer::new(client).with_rollback(true);
+
+        let output = runner.apply(&session);
+
+        assert!(matches!(output, Err(Error::CommandFailed { .. })));
+        assert_eq!(
+            runner.into_client().actions().last(),
+            Some(&Action::KillSession(SessionId::new("test")))
+        );
+    }
+
+    #[test]
+    fn quote_shell_words() {
+        assert_eq!(shell_quote("test:0.1"), "test:0.1");
+        assert_eq!(shell_quote("30%"), "30%");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("echo 'hi'"), r#"'echo '\''hi'\'''"#);
+    }
+
+    fn pane(index: usize, directory: &str, command: &str, active: bool) -> PaneInfo {
+        PaneInfo {
+            index,
+            directory: directory.to_string(),
+            command: command.to_string(),
+            command_line: String::new(),
+            active,
+            pid: 0,
+        }
+    }
+
+    #[test]
+    fn capture_running_session() {
+        let session_id = SessionId::new("live");
+        let client = RecordingClient::default()
+            .with_window(
+                session_id.clone(),
+                WindowInfo {
+                    index: 1,
+                    name: "editor".to_string(),
+                    layout: "5e4a,80x24,0,0{40x24,0,0,1,39x24,41,0,2}".to_string(),
+                    active: true,
+                },
+                vec![
+                    PaneInfo {
+                        command_line: "nvim src/main.rs".to_string(),
+                        ..pane(1, "/home/user", "nvim", false)
+                    },
+                    pane(2, "/home/user/code/logs", "zsh", true),
+                ],
+            )
+            .with_window(
+                session_id,
+                WindowInfo {
+                    index: 2,
+                    name: "shell".to_string(),
+                    layout: "b25d,80x24,0,0,3".to_string(),
+                    active: false,
+                },
+                vec![pane(1, "/tmp", "-bash", true)],
+            );
+        let mut runner = Muxer::new(client);
+
+        let session = temp_env::with_var("HOME", Some("/home/user"), || {
+            runner.capture("live").unwrap()
+        });
+
+        assert_eq!(
+            session,
+            Session::load_from_string(
+                "
+                name: live
+                directory: '~'
+                windows:
+                  - name: editor
+                    layout: 5e4a,80x24,0,0{40x24,0,0,1,39x24,41,0,2}
+                    panes:
+                      - command: nvim src/main.rs
+                      - directory: ~/code/logs
+                        focus: true
+                  - name: shell
+                    panes:
+                      - directory: /tmp
+                "
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn capture_missing_session() {
+        let mut runner = Muxer::new(RecordingClient::default());
+
+        let session = runner.capture("missing");
+
+        assert_eq!(session, Err(Error::SessionNotFound("missing".to_string())));
+    }
+
+    #[test]
+    fn parse_list_output() {
+        assert_eq!(
+            WindowInfo::parse("1\tmy editor\tb25d,80x24,0,0,3\t1"),
+            Some(WindowInfo {
+                index: 1,
+                name: "my editor".to_string(),
+                layout: "b25d,80x24,0,0,3".to_string(),
+                active: true,
+            })
+        );
+        assert_eq!(
+            PaneInfo::parse("0\t/tmp\tzsh\t0\t42\t"),
+            Some(PaneInfo {
+                pid: 42,
+                ..pane(0, "/tmp", "zsh", false)
+            })
+        );
+        assert_eq!(
+            PaneInfo::parse("0\t/tmp\tsleep\t1\t42\t\"sleep 1000 # \\\"a\tb\\\" \\$HOME\""),
+            Some(PaneInfo {
+                command_line: "sleep 1000 # \"a\tb\" $HOME".to_string(),
+                pid: 42,
+                ..pane(0, "/tmp", "sleep", true)
+            })
+        );
+        assert_eq!(
+            SessionInfo::parse("web\t2\t3\t1760000000\t/tp/tpl.yaml"),
+            Some(SessionInfo {
+                name: "web".to_string(),
+                attached: 2,
+                windows: 3,
+                loaded_at: Some(1760000000),
+                file: Some(PathBuf::from("/tp/tpl.yaml")),
+            })
+        );
+        assert_eq!(
+            SessionInfo::parse("scratch\t0\t1"),
+            Some(SessionInfo {
+                name: "scratch".to_string(),
+                attached: 0,
+                windows: 1,
+                loaded_at: None,
+                file: None,
+            })
+        );
+        assert_eq!(PaneInfo::parse("0\t/tmp\tzsh"), None);
+        assert_eq!(PaneInfo::parse("x\t/tmp\tzsh\t0\t42\t"), None);
+    }
+
+    fn window(index: usize, name: &str) -> WindowInfo {
+        WindowInfo {
+            index,
+            name: name.to_string(),
+            layout: String::new(),
+            active: false,
+        }
+    }
+
+    const SYNCED_SESSION: &str = "
+        name: test
+        windows:
+          - name: editor
+            layout: tiled
+            panes:
+              - command: nvim
+              - command: cargo watch
+          - name: logs
+            panes:
+              - command: tail -f log
+        ";
+
+    #[test]
+    fn sync_creates_missing_windows_and_panes() {
+        let session_id = SessionId::new("test");
+        let client = RecordingClient::default().with_window(
+            session_id,
+            window(3, "editor"),
+            vec![pane(0, "/", "nvim", true)],
+        );
+        let session: Session = Session::load_from_string(SYNCED_SESSION).unwrap();
+        let mut runner = Muxer::new(client).with_inside_tmux(true).with_sync(true);
 
         let output = runner.apply(&session).unwrap();
 
-        assert_eq!(output.windows, vec![(0, vec![0])]);
+        assert!(!output.is_new_session);
+        assert_eq!(output.windows, vec![(3, vec![0, 1]), (4, vec![0])]);
+        assert_eq!(
+            output.changes,
+            vec![Change::NewPane(3, 1), Change::NewWindow(4)]
+        );
+        let actions: Vec<String> = runner
+            .into_client()
+            .actions()
+            .iter()
+            .map(|action| action.to_string())
+            .collect();
+        assert_eq!(
+            actions[3..],
+            [
+                "tmux list-windows -F '#{window_index}\t#{window_name}\t#{window_layout}\t#{window_active}' -t =test",
+                "tmux list-panes -F '#{pane_index}\t#{pane_current_path}\t#{pane_current_command}\t#{pane_active}\t#{pane_pid}\t#{pane_start_command}' -t =test:3",
+                "tmux split-window -c . -t =test:3.0",
+                "tmux send-keys -t =test:3.1 'cargo watch' C-m",
+                "tmux select-layout -t =test:3 tiled",
+                "tmux new-window -c . -t =test:4",
+                "tmux rename-window -t =test:4 logs",
+                "tmux send-keys -t =test:4.0 'tail -f log' C-m",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn sync_reports_extra_windows_and_panes() {
+        let session_id = SessionId::new("test");
+        let shell = pane(0, "/", "zsh", true);
+        let panes = vec![
+            pane(0, "/", "nvim", true),
+            pane(1, "/", "zsh", false),
+            pane(2, "/", "zsh", false),
+        ];
+        let client = RecordingClient::default()
+            .with_window(session_id.clone(), window(0, "editor"), panes)
+            .with_window(session_id.clone(), window(1, "logs"), vec![shell.clone()])
+            .with_window(session_id, window(2, "scratch"), vec![shell]);
+        let session: Session = Session::load_from_string(SYNCED_SESSION).unwrap();
+
+        let mut runner = Muxer::new(client).with_inside_tmux(true).with_sync(true);
+        let output = runner.apply(&session).unwrap();
+
+        assert_eq!(
+            output.changes,
+            vec![Change::ExtraPane(0, 2), Change::ExtraWindow(2)]
+        );
+        assert!(
+            !runner
+                .into_client()
+                .actions()
+                .iter()
+                .any(|action| matches!(action, Action::KillPane(_) | Action::KillWindow(_)))
+        );
+    }
+
+    #[test]
+    fn sync_prunes_extra_windows_and_panes() {
+        let session_id = SessionId::new("test");
+        let shell = pane(0, "/", "zsh", true);
+        let panes = vec![
+            pane(0, "/", "nvim", true),
+            pane(1, "/", "zsh", false),
+            pane(2, "/", "zsh", false),
+            pane(3, "/", "zsh", false),
+        ];
+        let client = RecordingClient::default()
+            .with_window(session_id.clone(), window(0, "editor"), panes)
+            .with_window(session_id.clone(), window(1, "logs"), vec![shell.clone()])
+            .with_window(session_id, window(2, "scratch"), vec![shell]);
+        let session: Session = Session::load_from_string(SYNCED_SESSION).unwrap();
+
+        let mut runner = Muxer::new(client)
+            .with_inside_tmux(true)
+            .with_sync(true)
+            .with_prune(true);
+        let output = runner.apply(&session).unwrap();
+
+        assert_eq!(
+            output.changes,
+            vec![
+                Change::KilledPane(0, 3),
+                Change::KilledPane(0, 2),
+                Change::KilledWindow(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn mirror_a_running_session() {
+        let session_id = SessionId::new("test");
+        let running = || {
+            let panes = vec![pane(1, "/", "nvim", true), pane(2, "/", "zsh", false)];
+            RecordingClient::default()
+                .with_option(OptionName::new("base-index"), OptionValue::new("1"))
+                .with_option(OptionName::new("pane-base-index"), OptionValue::new("1"))
+                .with_window(session_id.clone(), window(1, "editor"), panes)
+                .with_window(session_id.clone(), window(2, "scratch"), vec![])
+        };
+        let session: Session = Session::load_from_string(SYNCED_SESSION).unwrap();
+        let actions = |client: RecordingClient| {
+            let mut runner = Muxer::new(client)
+                .with_inside_tmux(true)
+                .with_sync(true)
+                .with_prune(true);
+            runner.apply(&session).unwrap();
+            runner.into_client().actions().to_vec()
+        };
+
+        let mirror = RecordingClient::mirror(&mut running(), &session_id).unwrap();
+
+        assert_eq!(actions(mirror), actions(running()));
+        let stopped = RecordingClient::mirror(&mut RecordingClient::default(), &session_id);
+        assert!(stopped.unwrap().sessions.is_empty());
+    }
+
+    #[test]
+    fn sync_matches_windows_by_name() {
+        let session_id = SessionId::new("test");
+        let shell = pane(0, "/", "zsh", true);
+        let client = RecordingClient::default()
+            .with_window(session_id.clone(), window(0, "editor"), vec![shell.clone()])
+            .with_window(session_id.clone(), window(1, "zsh"), vec![shell.clone()])
+            .with_window(session_id.clone(), window(2, "logs"), vec![shell.clone()])
+            .with_window(session_id, window(3, "scratch"), vec![shell]);
+        let session = Session::load_from_string(
+            "
+            name: test
+            windows:
+              - name: editor
+              -
+              - name: server
+              - name: logs
+            ",
+        )
+        .unwrap();
+
+        let mut runner = Muxer::new(client)
+            .with_inside_tmux(true)
+            .with_sync(true)
+            .with_prune(true);
+        let output = runner.apply(&session).unwrap();
+
+        assert_eq!(
+            output.changes,
+            vec![Change::NewWindow(4), Change::KilledWindow(3)]
+        );
+    }
+
+    #[test]
+    fn set_session_and_window_options() {
+        let (output, actions) = apply(
+            RecordingClient::default(),
+            "
+            name: test
+            options:
+              mouse: true
+            global_options:
+              escape-time: 0
+            windows:
+              - options:
+                  remain-on-exit: on
+                panes:
+                  - command: cargo run
+            ",
+        );
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions[4..],
+            [
+                "tmux set-option -g escape-time 0",
+                "tmux set-option -t =test: mouse on",
+                "tmux set-window-option -t =test:0 remain-on-exit on",
+                "tmux send-keys -t =test:0.0 'cargo run' C-m",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_environment_from_session_to_pane() {
+        let (output, actions) = apply(
+            RecordingClient::default(),
+            "
+            name: test
+            env:
+              RUST_LOG: info
+              PORT: 3000
+            windows:
+              - env:
+                  PORT: 8080
+                panes:
+                  -
+                  - env:
+                      RUST_LOG: debug
+              - panes:
+                  - env:
+                      AWS_PROFILE: dev
+            ",
+        );
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions[1..],
+            [
+                "tmux new-session -d -c . -e PORT=8080 -e RUST_LOG=info -s test",
+                "tmux show-options -gv base-index",
+                "tmux show-options -gv pane-base-index",
+                "tmux set-environment -t =test PORT 3000",
+                "tmux set-environment -t =test RUST_LOG info",
+                "tmux split-window -c . -e PORT=8080 -e RUST_LOG=debug -t =test:0.0",
+                "tmux new-window -c . -e AWS_PROFILE=dev -e PORT=3000 -e RUST_LOG=info -t =test:1",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn run_lifecycle_hooks() {
+        let content = "
+            name: test
+            directory: /srv/app
+            hooks:
+              on_create: docker compose up -d
+              on_attach: git fetch
+              on_detach: \"docker compose stop # web\"
+            ";
+
+        let (output, actions) = apply(RecordingClient::default(), content);
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions,
+            [
+                "tmux has-session -t =test",
+                "(cd /srv/app && docker compose up -d)",
+                "tmux new-session -d -c /srv/app -s test",
+                "tmux show-options -gv base-index",
+                "tmux show-options -gv pane-base-index",
+                r#"tmux set-hook -t =test: client-detached 'if-shell -F '\''#{==:#{session_attached},0}'\'' '\''run-shell -b '\''\'\'''\''cd /srv/app && docker compose stop ## web'\''\'\'''\'''\'''"#,
+                "tmux switch-client -t =test",
+            ]
+        );
+
+        let client = RecordingClient::default().with_session(SessionId::new("test"));
+        let (output, actions) = apply(client, content);
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions,
+            [
+                "tmux has-session -t =test",
+                "(cd /srv/app && git fetch)",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_when_a_hook_fails() {
+        let client = RecordingClient::default()
+            .with_failure(|action| matches!(action, Action::RunShell { .. }));
+
+        let (output, actions) = apply(
+            client,
+            "
+            name: test
+            hooks:
+              on_create: exit 1
+            ",
+        );
+
+        assert_eq!(
+            output.err().map(|e| e.to_string()),
+            Some(
+                "`on_create` hook failed: `(cd . && exit 1)` failed with exit code 1: recorded failure"
+                    .to_string()
+            )
+        );
+        assert_eq!(actions, ["tmux has-session -t =test"]);
+    }
+
+    #[test]
+    fn stop_interrupts_busy_panes_before_killing() {
+        let session_id = SessionId::new("test");
+        let client = RecordingClient::default().with_window(
+            session_id,
+            window(0, "editor"),
+            vec![
+                pane(0, "/srv", "zsh", true),
+                pane(1, "/srv", "cargo", false),
+            ],
+        );
+        let session = Session::load_from_string(
+            "
+            name: test
+            hooks:
+              on_stop: docker compose stop
+            ",
+        )
+        .unwrap();
+        let mut runner = Muxer::new(client).with_stop_timeout(Duration::ZERO);
+
+        let output = runner.stop(&session);
+
+        assert_eq!(output, Ok(()));
+        assert_eq!(
+            runner
+                .into_client()
+                .actions()
+                .iter()
+                .map(|action| action.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "tmux has-session -t =test",
+                "(cd . && docker compose stop)",
+                "tmux list-windows -F '#{window_index}\t#{window_name}\t#{window_layout}\t#{window_active}' -t =test",
+                "tmux list-panes -F '#{pane_index}\t#{pane_current_path}\t#{pane_current_command}\t#{pane_active}\t#{pane_pid}\t#{pane_start_command}' -t =test:0",
+                "tmux send-keys -t =test:0.1 C-c",
+                "tmux kill-session -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_missing_session() {
+        let session = Session::load_from_string("name: test").unwrap();
+        let mut runner = Muxer::new(RecordingClient::default());
+
+        assert_eq!(
+            runner.stop(&session),
+            Err(Error::SessionNotFound("test".to_string()))
+        );
+    }
+
+    #[test]
+    fn stop_only_the_session_with_the_exact_name() {
+        let client = RecordingClient::default().with_session(SessionId::new("api-v2"));
+        let session = Session::load_from_string("name: api").unwrap();
+        let mut runner = Muxer::new(client);
+
+        let output = runner.stop(&session);
+
+        assert_eq!(output, Err(Error::SessionNotFound("api".to_string())));
+        assert_eq!(
+            runner
+                .into_client()
+                .actions()
+                .iter()
+                .map(|action| action.to_string())
+                .collect::<Vec<_>>(),
+            ["tmux has-session -t =api"]
+        );
+    }
+
+    #[test]
+    fn send_pre_commands_before_pane_commands() {
+        let (output, actions) = apply(
+            RecordingClient::default(),
+            "
+            name: test
+            pre:
+              - nvm use
+            windows:
+              - pre:
+                  - source .venv/bin/activate
+                panes:
+                  - commands:
+                      - make
+                      - make test
+                  -
+            ",
+        );
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions[4..],
+            [
+                "tmux send-keys -t =test:0.0 'nvm use' C-m",
+                "tmux send-keys -t =test:0.0 'source .venv/bin/activate' C-m",
+                "tmux send-keys -t =test:0.0 make C-m",
+                "tmux send-keys -t =test:0.0 'make test' C-m",
+                "tmux split-window -c . -t =test:0.0",
+                "tmux send-keys -t =test:0.1 'nvm use' C-m",
+                "tmux send-keys -t =test:0.1 'source .venv/bin/activate' C-m",
+                "tmux switch-client -t =test",
+            ]
+        );
+    }
+
+    #[test]
+    fn start_exec_panes_with_their_commands() {
+        let (output, actions) = apply(
+            RecordingClient::default(),
+            "
+            name: test
+            pre:
+              - nvm use
+            windows:
+              - panes:
+                  - command: npm start
+                    exec: true
+                  - command: npm test
+              - panes:
+                  - command: tail -f log
+                    exec: true
+                    remain_on_exit: true
+            ",
+        );
+
+        assert!(output.is_ok());
+        assert_eq!(
+            actions[1..],
+            [
+                "tmux new-session -d -c . -s test 'nvm use && npm start'",
+                "tmux show-options -gv base-index",
+                "tmux show-options -gv pane-base-index",
+                "tmux split-window -c . -t =test:0.0",
+                "tmux send-keys -t =test:0.1 'nvm use' C-m",
+                "tmux send-keys -t =test:0.1 'npm test' C-m",
+                "tmux new-window -c . -t =test:1 'nvm use && tail -f log' ';' set-option -p remain-on-exit on",
+                "tmux switch-client -t =test",
+            ]
+        );
     }
 }
@@ -0,0 +1,137 @@
+/// Replace the `{{ name }}` and `${NAME:-default}` placeholders of `text` with
+/// the values `lookup` finds, pushing the names it can't find to `undefined`.
+///
+/// `$${` is kept as a literal `${`, for commands using shell parameter
+/// expansion. Placeholders that aren't plain variable names are left as is.
+pub fn render(
+    text: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    undefined: &mut Vec<String>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['$', '{']) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            output.push_str("${");
+            rest = after;
+            continue;
+        }
+
+        let placeholder = if rest.starts_with("${") {
+            rest.find('}')
+                .map(|end| (end + 1, parse_parameter(&rest[2..end])))
+        } else if rest.starts_with("{{") {
+            rest.find("}}")
+                .map(|end| (end + 2, parse_variable(&rest[2..end])))
+        } else {
+            None
+        };
+
+        match placeholder {
+            Some((end, Some((name, default)))) => {
+                let value = lookup(name)
+                    .filter(|value| !value.is_empty())
+                    .or(default.map(str::to_string));
+                match value {
+                    Some(value) => output.push_str(&value),
+                    None => {
+                        undefined.push(name.to_string());
+                        output.push_str(&rest[..end]);
+                    }
+                }
+                rest = &rest[end..];
+            }
+            Some((end, None)) => {
+                output.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+            None => {
+                let next = rest.chars().next().map_or(0, char::len_utf8);
+                output.push_str(&rest[..next]);
+                rest = &rest[next..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+// `NAME` or `NAME:-default`.
+fn parse_parameter(inner: &str) -> Option<(&str, Option<&str>)> {
+    let (name, default) = match inner.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (inner, None),
+    };
+    is_name(name).then_some((name, default))
+}
+
+// ` name `.
+fn parse_variable(inner: &str) -> Option<(&str, Option<&str>)> {
+    let name = inner.trim();
+    is_name(name).then_some((name, None))
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "project" => Some("api".to_string()),
+            "PORT" => Some("9000".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    fn render_all(text: &str) -> (String, Vec<String>) {
+        let mut undefined = vec![];
+        let output = render(text, lookup, &mut undefined);
+        (output, undefined)
+    }
+
+    #[test]
+    fn replace_placeholders() {
+        assert_eq!(
+            render_all("~/code/{{ project }} on ${PORT}"),
+            ("~/code/api on 9000".to_string(), vec![])
+        );
+        assert_eq!(
+            render_all("{{project}}:${HOST:-localhost}:${EMPTY:-80}"),
+            ("api:localhost:80".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn report_undefined_variables() {
+        assert_eq!(
+            render_all("{{ branch }} ${HOST}"),
+            (
+                "{{ branch }} ${HOST}".to_string(),
+                vec!["branch".to_string(), "HOST".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn keep_shell_syntax() {
+        assert_eq!(
+            render_all("for f in *; do echo $${f} ${f%.rs} $HOME; done {"),
+            (
+                "for f in *; do echo ${f} ${f%.rs} $HOME; done {".to_string(),
+                vec![]
+            )
+        );
+    }
+}
@@ -1,126 +1,298 @@
-use std::process::{Command, Stdio};
+use std::{
+    fs,
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+};
 use tp::muxer::{
-    Client, Error, Keys, Layout, OptionName, OptionValue, PaneID, SessionId, WindowID, WindowName,
+    Action, Client, Environment, Error, Keys, Layout, OptionName, OptionScope, OptionValue, PaneID,
+    PaneInfo, SessionId, SessionInfo, ShellCommand, Split, WindowID, WindowInfo, WindowName,
+    shell_quote,
 };
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct TmuxClient;
 
-impl Client for TmuxClient {
-    fn get_option(&mut self, option_name: &OptionName) -> Result<OptionValue, Error> {
-        let output = Command::new("tmux")
-            .args(["show-options", "-gv", option_name.value()])
-            .stderr(Stdio::null())
+impl TmuxClient {
+    fn run(&self, action: Action) -> Result<String, Error> {
+        self.run_with_flags(action, &[])
+    }
+
+    fn run_with_flags(&self, action: Action, flags: &[&str]) -> Result<String, Error> {
+        let output = Command::new(action.program())
+            .args(flags)
+            .args(action.args())
+            .stdin(Stdio::null())
             .output()
-            .map_err(|e| Error::OptionNotFound(e.to_string()))?;
+            .map_err(|e| Error::UnableToRunCommand {
+                command: action.to_string(),
+                reason: e.to_string(),
+            })?;
+
+        if !output.status.success() {
+            return Err(Error::CommandFailed {
+                command: action.to_string(),
+                code: output.status.code(),
+                stderr: String::from_utf8_lossy(output.stderr.trim_ascii()).into_owned(),
+            });
+        }
 
-        let value = str::from_utf8(output.stdout.trim_ascii())
-            .map_err(|e| Error::OptionNotFound(e.to_string()))?;
+        Ok(String::from_utf8_lossy(output.stdout.trim_ascii()).into_owned())
+    }
+
+    fn run_list<T>(&self, action: Action, parse: fn(&str) -> Option<T>) -> Result<Vec<T>, Error> {
+        // Without `-u`, tmux prints the tab separators as `_` unless the
+        // locale says the terminal supports UTF-8.
+        let output = self.run_with_flags(action.clone(), &["-u"])?;
+        output
+            .lines()
+            .map(|line| {
+                parse(line).ok_or_else(|| Error::UnexpectedOutput {
+                    command: action.to_string(),
+                    output: line.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+// The command line of the program the shell `pid` runs in the foreground,
+// e.g. one sent with `send-keys`. Only available where `/proc` lists children.
+fn foreground_command_line(pid: u32) -> Option<String> {
+    let children = fs::read_to_string(format!("/proc/{pid}/task/{pid}/children")).ok()?;
+    let child = children.split_whitespace().last()?;
+    let cmdline = fs::read(format!("/proc/{child}/cmdline")).ok()?;
+    let args: Vec<_> = cmdline
+        .split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| shell_quote(&String::from_utf8_lossy(arg)))
+        .collect();
+    (!args.is_empty()).then(|| args.join(" "))
+}
+
+impl Client for TmuxClient {
+    fn get_option(&mut self, option_name: &OptionName) -> Result<OptionValue, Error> {
+        let value = self.run(Action::ShowOption(option_name.clone()))?;
+        if value.is_empty() {
+            return Err(Error::OptionNotFound(option_name.value().to_string()));
+        }
 
         Ok(OptionValue::new(value))
     }
 
-    fn set_option(&mut self, option_name: &OptionName, option_value: &OptionValue) {
-        let (_, _) = (option_name, option_value);
-        todo!()
-    }
-
-    fn new_session(&mut self, session_id: &SessionId, directory: &str) {
-        let _ = Command::new("tmux")
-            .args([
-                "new-session",
-                "-d",
-                "-c",
-                directory,
-                "-s",
-                &session_id.to_string(),
-            ])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-    }
-
-    fn switch_to_session(&mut self, session_id: &SessionId) {
-        let _ = Command::new("tmux")
-            .args(["switch-client", "-t", &session_id.to_string()])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-    }
-
-    fn has_session(&mut self, session_id: &SessionId) -> bool {
-        let output = Command::new("tmux")
-            .args(["has-session", "-t", &session_id.to_string()])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status();
-
-        match output {
-            Ok(status) => status.success(),
-            _ => false,
+    fn set_option(
+        &mut self,
+        scope: &OptionScope,
+        option_name: &OptionName,
+        option_value: &OptionValue,
+    ) -> Result<(), Error> {
+        self.run(Action::SetOption {
+            scope: scope.clone(),
+            option_name: option_name.clone(),
+            option_value: option_value.clone(),
+        })?;
+        Ok(())
+    }
+
+    fn new_session(
+        &mut self,
+        session_id: &SessionId,
+        directory: &str,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error> {
+        self.run(Action::NewSession {
+            session_id: session_id.clone(),
+            directory: directory.to_string(),
+            env: env.clone(),
+            command: command.cloned(),
+        })?;
+        Ok(())
+    }
+
+    fn set_environment(
+        &mut self,
+        session_id: &SessionId,
+        name: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        self.run(Action::SetEnvironment {
+            session_id: session_id.clone(),
+            name: name.to_string(),
+            value: value.to_string(),
+        })?;
+        Ok(())
+    }
+
+    fn kill_session(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        self.run(Action::KillSession(session_id.clone()))?;
+        Ok(())
+    }
+
+    fn switch_to_session(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        self.run(Action::SwitchClient(session_id.clone()))?;
+        Ok(())
+    }
+
+    fn attach_session(&mut self, session_id: &SessionId) -> Result<(), Error> {
+        let action = Action::AttachSession(session_id.clone());
+        // `exec` only returns if tmux could not be started.
+        let error = Command::new(action.program()).args(action.args()).exec();
+        Err(Error::UnableToRunCommand {
+            command: action.to_string(),
+            reason: error.to_string(),
+        })
+    }
+
+    fn has_session(&mut self, session_id: &SessionId) -> Result<bool, Error> {
+        match self.run(Action::HasSession(session_id.clone())) {
+            Ok(_) => Ok(true),
+            Err(Error::CommandFailed { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn list_sessions(&mut self) -> Result<Vec<SessionInfo>, Error> {
+        // `list-sessions` fails when no tmux server is running.
+        match self.run_list(Action::ListSessions, SessionInfo::parse) {
+            Err(Error::CommandFailed { .. }) => Ok(vec![]),
+            sessions => sessions,
+        }
+    }
+
+    fn list_windows(&mut self, session_id: &SessionId) -> Result<Vec<WindowInfo>, Error> {
+        self.run_list(Action::ListWindows(session_id.clone()), WindowInfo::parse)
+    }
+
+    fn new_window(
+        &mut self,
+        window_id: &WindowID,
+        directory: &str,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error> {
+        self.run(Action::NewWindow {
+            window_id: window_id.clone(),
+            directory: directory.to_string(),
+            env: env.clone(),
+            command: command.cloned(),
+        })?;
+        Ok(())
+    }
+
+    fn kill_window(&mut self, window_id: &WindowID) -> Result<(), Error> {
+        self.run(Action::KillWindow(window_id.clone()))?;
+        Ok(())
+    }
+
+    fn rename_window(
+        &mut self,
+        window_id: &WindowID,
+        window_name: &WindowName,
+    ) -> Result<(), Error> {
+        self.run(Action::RenameWindow {
+            window_id: window_id.clone(),
+            window_name: window_name.clone(),
+        })?;
+        Ok(())
+    }
+
+    fn list_panes(&mut self, window_id: &WindowID) -> Result<Vec<PaneInfo>, Error> {
+        let mut panes = self.run_list(Action::ListPanes(window_id.clone()), PaneInfo::parse)?;
+        for pane in &mut panes {
+            if pane.command_line.is_empty() {
+                pane.command_line = foreground_command_line(pane.pid).unwrap_or_default();
+            }
+        }
+        Ok(panes)
+    }
+
+    fn new_pane(
+        &mut self,
+        pane_id: &PaneID,
+        directory: &str,
+        split: &Split,
+        env: &Environment,
+        command: Option<&ShellCommand>,
+    ) -> Result<(), Error> {
+        self.run(Action::SplitWindow {
+            pane_id: pane_id.clone(),
+            directory: directory.to_string(),
+            split: split.clone(),
+            env: env.clone(),
+            command: command.cloned(),
+        })?;
+        Ok(())
+    }
+
+    fn kill_pane(&mut self, pane_id: &PaneID) -> Result<(), Error> {
+        self.run(Action::KillPane(pane_id.clone()))?;
+        Ok(())
+    }
+
+    fn select_pane(&mut self, pane_id: &PaneID) -> Result<(), Error> {
+        self.run(Action::SelectWindow(pane_id.window().clone()))?;
+        self.run(Action::SelectPane(pane_id.clone()))?;
+        Ok(())
+    }
+
+    fn send_keys(&mut self, pane_id: &PaneID, keys: Keys) -> Result<(), Error> {
+        self.run(Action::SendKeys {
+            pane_id: pane_id.clone(),
+            keys,
+        })?;
+        Ok(())
+    }
+
+    fn interrupt(&mut self, pane_id: &PaneID) -> Result<(), Error> {
+        self.run(Action::SendInterrupt(pane_id.clone()))?;
+        Ok(())
+    }
+
+    fn use_layout(&mut self, window_id: &WindowID, layout: &Layout) -> Result<(), Error> {
+        self.run(Action::SelectLayout {
+            window_id: window_id.clone(),
+            layout: layout.clone(),
+        })?;
+        Ok(())
+    }
+
+    fn run_shell(&mut self, directory: &str, command: &str) -> Result<(), Error> {
+        let action = Action::RunShell {
+            directory: directory.to_string(),
+            command: command.to_string(),
+        };
+        // Hook output goes straight to the terminal instead of being captured.
+        let status = Command::new(action.program())
+            .args(action.args())
+            .current_dir(directory)
+            .stdin(Stdio::null())
+            .status()
+            .map_err(|e| Error::UnableToRunCommand {
+                command: action.to_string(),
+                reason: e.to_string(),
+            })?;
+
+        if !status.success() {
+            return Err(Error::CommandFailed {
+                command: action.to_string(),
+                code: status.code(),
+                stderr: String::new(),
+            });
         }
+        Ok(())
     }
 
-    fn new_window(&mut self, session_id: &SessionId, directory: &str) {
-        let _ = Command::new("tmux")
-            .args(["new-window", "-c", directory, "-t", &session_id.to_string()])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-    }
-
-    fn rename_window(&mut self, window_id: &WindowID, window_name: &WindowName) {
-        let _ = Command::new("tmux")
-            .args([
-                "rename-window",
-                "-t",
-                &window_id.to_string(),
-                window_name.value(),
-            ])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-    }
-
-    fn new_pane(&mut self, window_id: &WindowID, directory: &str) {
-        let _ = Command::new("tmux")
-            .args([
-                "split-window",
-                "-c",
-                directory,
-                "-t",
-                &window_id.to_string(),
-            ])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-    }
-
-    fn select_pane(&mut self, pane_id: &PaneID) {
-        let window_id = pane_id.window_id();
-        let _ = Command::new("tmux")
-            .args(["select-window", "-t", &window_id.to_string()])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-
-        let _ = Command::new("tmux")
-            .args(["select-pane", "-t", &pane_id.to_string()])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-    }
-
-    fn send_keys(&mut self, pane_id: &PaneID, keys: Keys) {
-        let _ = Command::new("tmux")
-            .args(["send-keys", "-t", &pane_id.to_string(), keys.value(), "C-m"])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .output();
-    }
-
-    fn use_layout(&mut self, layout: &Layout) {
-        let _ = layout;
-        todo!()
+    fn set_hook(
+        &mut self,
+        session_id: &SessionId,
+        hook_name: &str,
+        command: &str,
+    ) -> Result<(), Error> {
+        self.run(Action::SetHook {
+            session_id: session_id.clone(),
+            hook_name: hook_name.to_string(),
+            command: command.to_string(),
+        })?;
+        Ok(())
     }
 }
@@ -0,0 +1,760 @@
+use crate::{
+    config::{Format, Origins, Session},
+    muxer::expand_tilde,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+use yaml_rust2::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::Marker,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A line and a column of a session file, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    fn from_offset(content: &str, offset: usize) -> Self {
+        let before = &content[..offset.min(content.len())];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// A problem found in a session file by [`Session::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The session file, unless it could not be found.
+    pub path: Option<PathBuf>,
+    pub location: Option<Location>,
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(path: Option<&Path>, severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            path: path.map(Path::to_path_buf),
+            location: None,
+            severity,
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+
+    fn at(mut self, location: Option<Location>) -> Self {
+        self.location = location;
+        self
+    }
+
+    fn suggest(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some(Location { line, column }) = self.location {
+            write!(f, "{line}:{column}:")?;
+        }
+        if self.path.is_some() || self.location.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n    help: {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A step of the path from the top of a session file to one of its values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+impl Session {
+    /// Check the session file `name`, with its template `variables`, for
+    /// problems that would make loading it fail or behave unexpectedly.
+    /// Undefined variables are reported with the `undefined` severity: an
+    /// error when loading, a warning when they may be given at load time.
+    pub fn validate(
+        name: impl AsRef<str>,
+        variables: &BTreeMap<String, String>,
+        undefined: Severity,
+    ) -> Vec<Diagnostic> {
+        let name = name.as_ref();
+        let path = match Self::path(name) {
+            Ok(path) => path,
+            Err(e) => return vec![Diagnostic::new(None, Severity::Error, e.to_string())],
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => return vec![Diagnostic::new(Some(&path), Severity::Error, e.to_string())],
+        };
+        let format = Format::from_path(&path).unwrap_or_default();
+
+        let (session, origins) = match Self::load_with_origins(name) {
+            Ok(loaded) => loaded,
+            Err(e) => return vec![parse_error(&path, format, &content, e.to_string())],
+        };
+
+        let mut diagnostics = vec![];
+        let session = match session.interpolate(variables) {
+            Ok(session) => session,
+            Err(e) => {
+                diagnostics.push(
+                    Diagnostic::new(Some(&path), undefined, e.to_string()).suggest(
+                        "define them in `vars`, the environment, or pass them after \
+                         the session as `-- name=value`",
+                    ),
+                );
+                session
+            }
+        };
+
+        // Values of the files extended or included are located in those files.
+        let mut locators = HashMap::from([(path.clone(), Locator::new(format, &content))]);
+        for (segments, diagnostic) in check(&session) {
+            let (file, segments) = origin(&origins, &path, segments);
+            let locator = locators.entry(file.to_path_buf()).or_insert_with(|| {
+                let format = Format::from_path(file).unwrap_or_default();
+                let content = fs::read_to_string(file).ok()?;
+                Locator::new(format, &content)
+            });
+            let location = locator.as_ref().and_then(|locator| locator.find(&segments));
+            diagnostics.push(Diagnostic {
+                path: Some(file.to_path_buf()),
+                ..diagnostic.at(location)
+            });
+        }
+        diagnostics
+    }
+}
+
+// The file a value of the merged session comes from, `path` unless it comes
+// from a file extended or included, and the path to the value in that file.
+fn origin<'a>(
+    origins: &'a Origins,
+    path: &'a Path,
+    mut segments: Vec<Segment<'static>>,
+) -> (&'a Path, Vec<Segment<'static>>) {
+    match segments[..] {
+        [Segment::Key("directory")] => (origins.directory.as_deref().unwrap_or(path), segments),
+        [Segment::Key("windows"), Segment::Index(wid), ..] => match origins.windows.get(wid) {
+            Some((file, index)) => {
+                segments[1] = Segment::Index(*index);
+                (file, segments)
+            }
+            None => (path, segments),
+        },
+        _ => (path, segments),
+    }
+}
+
+// The located error of the session file itself, falling back to the error of
+// the merged session when the problem comes from a file it extends or includes.
+fn parse_error(path: &Path, format: Format, content: &str, error: String) -> Diagnostic {
+    let (message, location) = parse_file::<Session>(format, content).unwrap_or((error, None));
+    let message = match message.find(" at line ") {
+        Some(index) if location.is_some() => message[..index].to_string(),
+        _ => message,
+    };
+    let suggestion = suggest_field(&message).map(|field| format!("did you mean `{field}`?"));
+    Diagnostic {
+        suggestion,
+        ..Diagnostic::new(Some(path), Severity::Error, message).at(location)
+    }
+}
+
+// The message and location of the error of parsing `content`, if any.
+fn parse_file<T: DeserializeOwned>(
+    format: Format,
+    content: &str,
+) -> Option<(String, Option<Location>)> {
+    match format {
+        Format::Yaml => serde_yaml::from_str::<T>(content).err().map(|e| {
+            let location = e.location().map(|location| Location {
+                line: location.line(),
+                column: location.column(),
+            });
+            (e.to_string(), location)
+        }),
+        Format::Toml => toml::from_str::<T>(content).err().map(|e| {
+            let location = e
+                .span()
+                .map(|span| Location::from_offset(content, span.start));
+            (e.message().to_string(), location)
+        }),
+        Format::Json => serde_json::from_str::<T>(content).err().map(|e| {
+            let location = Location {
+                line: e.line(),
+                column: e.column(),
+            };
+            (e.to_string(), Some(location))
+        }),
+    }
+}
+
+// The known field closest to the unknown one of a serde message like
+// "unknown field `focsu`, expected one of `focus`, `directory`".
+fn suggest_field(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once("unknown field `")?;
+    let (unknown, expected) = rest.split_once('`')?;
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|field| (edit_distance(unknown, field), field))
+        .filter(|&(distance, field)| distance <= field.len() / 3 + 1)
+        .min()
+        .map(|(_, field)| field)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// The problems of a session that parses, with the path to the value at fault.
+fn check(session: &Session) -> Vec<(Vec<Segment<'static>>, Diagnostic)> {
+    use Segment::{Index, Key};
+
+    let mut diagnostics = vec![];
+    let mut missing_directory = |segments: Vec<Segment<'static>>, directory: &Option<PathBuf>| {
+        let Some(directory) = directory.as_ref().map(expand_tilde) else {
+            return;
+        };
+        if directory.is_absolute() && !directory.is_dir() {
+            let message = format!("directory `{}` does not exist", directory.display());
+            let diagnostic = Diagnostic::new(None, Severity::Warning, message)
+                .suggest("create the directory or fix the path");
+            diagnostics.push((segments, diagnostic));
+        }
+    };
+
+    missing_directory(vec![Key("directory")], &session.directory);
+    for (wid, window) in session.windows.iter().enumerate() {
+        missing_directory(
+            vec![Key("windows"), Index(wid), Key("directory")],
+            &window.directory,
+        );
+        for (pid, pane) in window.panes.iter().enumerate() {
+            missing_directory(
+                vec![
+                    Key("windows"),
+                    Index(wid),
+                    Key("panes"),
+                    Index(pid),
+                    Key("directory"),
+                ],
+                &pane.directory,
+            );
+        }
+    }
+
+    let mut names = HashMap::new();
+    for (wid, window) in session.windows.iter().enumerate() {
+        let Some(name) = &window.name else {
+            continue;
+        };
+        if let Some(&first) = names.get(name) {
+            let message = format!("window {wid} has the same name as window {first}, `{name}`");
+            let diagnostic = Diagnostic::new(None, Severity::Warning, message)
+                .suggest("give each window a unique name so it can be targeted by name");
+            diagnostics.push((vec![Key("windows"), Index(wid), Key("name")], diagnostic));
+        } else {
+            names.insert(name, wid);
+        }
+
+        for (pid, pane) in window.panes.iter().enumerate() {
+            let Some(target) = pane.target.filter(|&target| target >= pid) else {
+                continue;
+            };
+            let message = format!(
+                "pane {pid} of window {wid} targets pane {target}, which is not created before it"
+            );
+            let suggestion = match pid {
+                0 => "remove `target` from the first pane".to_string(),
+                _ => format!("target a pane from 0 to {}", pid - 1),
+            };
+            let diagnostic = Diagnostic::new(None, Severity::Error, message).suggest(suggestion);
+            let segments = vec![
+                Key("windows"),
+                Index(wid),
+                Key("panes"),
+                Index(pid),
+                Key("target"),
+            ];
+            diagnostics.push((segments, diagnostic));
+        }
+    }
+
+    let focused: Vec<_> = session
+        .windows
+        .iter()
+        .enumerate()
+        .flat_map(|(wid, window)| {
+            let panes = window.panes.iter().enumerate();
+            panes.filter_map(move |(pid, pane)| pane.focus.then_some((wid, pid)))
+        })
+        .collect();
+    if let [(wid, pid), .., (last_wid, last_pid)] = focused[..] {
+        let message = format!(
+            "{} panes have `focus` set, only pane {last_pid} of window {last_wid} is focused",
+            focused.len()
+        );
+        let diagnostic = Diagnostic::new(None, Severity::Warning, message)
+            .suggest("set `focus` on a single pane");
+        let segments = vec![
+            Key("windows"),
+            Index(wid),
+            Key("panes"),
+            Index(pid),
+            Key("focus"),
+        ];
+        diagnostics.push((segments, diagnostic));
+    }
+
+    diagnostics
+}
+
+/// Finds where the values of a session file are, by their path from the top.
+enum Locator {
+    // JSON files are read as YAML, which they are a subset of.
+    Yaml(Node),
+    Toml(String, toml_edit::ImDocument<String>),
+}
+
+impl Locator {
+    fn new(format: Format, content: &str) -> Option<Self> {
+        match format {
+            Format::Yaml | Format::Json => {
+                let mut builder = NodeBuilder::default();
+                Parser::new_from_str(content)
+                    .load(&mut builder, false)
+                    .ok()?;
+                builder.root.map(Self::Yaml)
+            }
+            Format::Toml => {
+                let document = toml_edit::ImDocument::parse(content.to_string()).ok()?;
+                Some(Self::Toml(content.to_string(), document))
+            }
+        }
+    }
+
+    fn find(&self, segments: &[Segment]) -> Option<Location> {
+        match self {
+            Self::Yaml(node) => node.find(segments),
+            Self::Toml(content, document) => {
+                let span = find_toml(document.as_item(), segments)?;
+                Some(Location::from_offset(content, span.start))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Mapping(Location, Vec<(String, Location, Node)>),
+    Sequence(Location, Vec<Node>),
+    Scalar(Location),
+}
+
+impl Node {
+    fn location(&self) -> Location {
+        match self {
+            Self::Mapping(location, _) | Self::Sequence(location, _) | Self::Scalar(location) => {
+                *location
+            }
+        }
+    }
+
+    // The location of the key of the last segment, or of the value itself.
+    fn find(&self, segments: &[Segment]) -> Option<Location> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return Some(self.location());
+        };
+        match (self, segment) {
+            (Self::Mapping(_, entries), Segment::Key(key)) => {
+                let (_, location, node) = entries.iter().find(|(name, ..)| name == key)?;
+                if rest.is_empty() {
+                    return Some(*location);
+                }
+                node.find(rest)
+            }
+            (Self::Sequence(_, items), Segment::Index(index)) => items.get(*index)?.find(rest),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct NodeBuilder {
+    stack: Vec<Frame>,
+    root: Option<Node>,
+}
+
+enum Frame {
+    Mapping {
+        location: Location,
+        entries: Vec<(String, Location, Node)>,
+        key: Option<(String, Location)>,
+    },
+    Sequence {
+        location: Location,
+        items: Vec<Node>,
+    },
+}
+
+impl NodeBuilder {
+    fn push(&mut self, node: Node, text: Option<String>) {
+        match self.stack.last_mut() {
+            None => self.root = Some(node),
+            Some(Frame::Sequence { items, .. }) => items.push(node),
+            Some(Frame::Mapping { entries, key, .. }) => match key.take() {
+                Some((name, location)) => entries.push((name, location, node)),
+                None => *key = Some((text.unwrap_or_default(), node.location())),
+            },
+        }
+    }
+}
+
+impl MarkedEventReceiver for NodeBuilder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        let location = Location {
+            line: mark.line(),
+            column: mark.col() + 1,
+        };
+        match event {
+            Event::MappingStart(..) => self.stack.push(Frame::Mapping {
+                location,
+                entries: vec![],
+                key: None,
+            }),
+            Event::SequenceStart(..) => self.stack.push(Frame::Sequence {
+                location,
+                items: vec![],
+            }),
+            Event::MappingEnd | Event::SequenceEnd => {
+                let node = match self.stack.pop() {
+                    Some(Frame::Mapping {
+                        location, entries, ..
+                    }) => Node::Mapping(location, entries),
+                    Some(Frame::Sequence { location, items }) => Node::Sequence(location, items),
+                    None => return,
+                };
+                self.push(node, None);
+            }
+            Event::Scalar(text, ..) => self.push(Node::Scalar(location), Some(text)),
+            Event::Alias(_) => self.push(Node::Scalar(location), None),
+            _ => {}
+        }
+    }
+}
+
+// The span of the key of the last segment, or of the value itself.
+fn find_toml(item: &toml_edit::Item, segments: &[Segment]) -> Option<std::ops::Range<usize>> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return item.span();
+    };
+    match segment {
+        Segment::Key(name) => {
+            let (key, value) = item.as_table_like()?.get_key_value(name)?;
+            if rest.is_empty() {
+                return key.span();
+            }
+            find_toml(value, rest)
+        }
+        Segment::Index(index) => match item {
+            toml_edit::Item::ArrayOfTables(tables) => {
+                let table = tables.get(*index)?;
+                find_toml(&toml_edit::Item::Table(table.clone()), rest)
+            }
+            _ => {
+                let value = item.as_array()?.get(*index)?;
+                find_toml(&toml_edit::Item::Value(value.clone()), rest)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn validate(file_name: &str, content: &str) -> Vec<Diagnostic> {
+        validate_with(file_name, content, &BTreeMap::new(), Severity::Error)
+    }
+
+    fn validate_with(
+        file_name: &str,
+        content: &str,
+        variables: &BTreeMap<String, String>,
+        undefined: Severity,
+    ) -> Vec<Diagnostic> {
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let tmp_dir = temp_test_dir.path();
+        fs::write(tmp_dir.join(file_name), content).unwrap();
+        let name = file_name.split('.').next().unwrap();
+        temp_env::with_vars(
+            [
+                ("TP_SESSIONS_DIR", Some(tmp_dir.as_os_str())),
+                ("TP_SESSIONS_PATH", None),
+            ],
+            || Session::validate(name, variables, undefined),
+        )
+    }
+
+    // The diagnostics without their path and suggestion.
+    fn summary(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| Diagnostic {
+                path: None,
+                suggestion: None,
+                ..diagnostic.clone()
+            })
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn locate_parse_errors() {
+        let diagnostics = validate("api.yaml", "name: api\nwindwos: []\n");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].location,
+            Some(Location { line: 2, column: 1 })
+        );
+        assert!(
+            diagnostics[0]
+                .message
+                .starts_with("unknown field `windwos`, expected one of")
+        );
+        assert_eq!(
+            diagnostics[0].suggestion.as_deref(),
+            Some("did you mean `windows`?")
+        );
+
+        let diagnostics = validate("api.toml", "name = \"api\"\nwindows = 1\n");
+        assert_eq!(
+            summary(&diagnostics),
+            vec!["2:11: error: invalid type: integer `1`, expected a sequence"]
+        );
+    }
+
+    #[test]
+    fn check_session_files() {
+        let content = "
+name: api
+directory: /tp/missing
+windows:
+  - name: editor
+    panes:
+      - focus: true
+      - target: 1
+  - name: editor
+    panes:
+      - focus: true
+";
+
+        let diagnostics = validate("api.yaml", content);
+
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                "3:1: warning: directory `/tp/missing` does not exist",
+                "8:9: error: pane 1 of window 0 targets pane 1, which is not created before it",
+                "9:5: warning: window 1 has the same name as window 0, `editor`",
+                "7:9: warning: 2 panes have `focus` set, only pane 0 of window 1 is focused",
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            format!(
+                "{}:3:1: warning: directory `/tp/missing` does not exist\n    \
+                 help: create the directory or fix the path",
+                diagnostics[0].path.as_ref().unwrap().display()
+            )
+        );
+    }
+
+    #[test]
+    fn locate_toml_values() {
+        let content = r#"name = "api"
+
+[[windows]]
+name = "editor"
+
+[[windows]]
+name = "editor"
+panes = [{ target = 0 }]
+"#;
+
+        let diagnostics = validate("api.toml", content);
+
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                "7:1: warning: window 1 has the same name as window 0, `editor`",
+                "8:12: error: pane 0 of window 1 targets pane 0, which is not created before it",
+            ]
+        );
+    }
+
+    #[test]
+    fn locate_values_in_the_files_they_come_from() {
+        let base = "
+name: base
+windows:
+  - name: editor
+    directory: /tp/missing
+  - name: shell
+";
+        let api = "
+name: api
+extends: base
+windows:
+  - name: server
+    panes:
+      - target: 0
+";
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let tmp_dir = temp_test_dir.path();
+        fs::write(tmp_dir.join("base.yaml"), base).unwrap();
+        fs::write(tmp_dir.join("api.yaml"), api).unwrap();
+
+        let diagnostics = temp_env::with_vars(
+            [
+                ("TP_SESSIONS_DIR", Some(tmp_dir.as_os_str())),
+                ("TP_SESSIONS_PATH", None),
+            ],
+            || Session::validate("api", &BTreeMap::new(), Severity::Error),
+        );
+
+        let files: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.path.as_deref().and_then(Path::file_name))
+            .collect();
+        assert_eq!(
+            files,
+            vec![Some("base.yaml".as_ref()), Some("api.yaml".as_ref())]
+        );
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                "5:5: warning: directory `/tp/missing` does not exist",
+                "7:9: error: pane 0 of window 2 targets pane 0, which is not created before it",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_sessions_defined_by_several_files() {
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let tmp_dir = temp_test_dir.path();
+        fs::write(tmp_dir.join("api.yaml"), "name: api").unwrap();
+        fs::write(tmp_dir.join("api.toml"), "name = \"api\"").unwrap();
+
+        let diagnostics = temp_env::with_vars(
+            [
+                ("TP_SESSIONS_DIR", Some(tmp_dir.as_os_str())),
+                ("TP_SESSIONS_PATH", None),
+            ],
+            || Session::validate("api", &BTreeMap::new(), Severity::Error),
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert!(
+            diagnostics[0]
+                .message
+                .starts_with("session `api` is defined by several files")
+        );
+    }
+
+    #[test]
+    fn report_missing_sessions_and_undefined_variables() {
+        let temp_test_dir = tempdir().expect("Failed to create temporary directory");
+        let diagnostics = temp_env::with_vars(
+            [
+                ("TP_SESSIONS_DIR", Some(temp_test_dir.path().as_os_str())),
+                ("TP_SESSIONS_PATH", None),
+            ],
+            || Session::validate("missing", &BTreeMap::new(), Severity::Error),
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, None);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+
+        let diagnostics = validate("api.yaml", "name: '{{ project }}'");
+        assert_eq!(
+            summary(&diagnostics),
+            vec!["error: undefined variables: project"]
+        );
+
+        let diagnostics = validate("api.yaml", "name: api");
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn check_templated_session_files_with_their_variables() {
+        let content = "name: '{{ project }}'";
+
+        let variables = BTreeMap::from([("project".to_string(), "web".to_string())]);
+        let diagnostics = validate_with("tpl.yaml", content, &variables, Severity::Error);
+        assert!(diagnostics.is_empty());
+
+        let diagnostics = validate_with("tpl.yaml", content, &BTreeMap::new(), Severity::Warning);
+        assert_eq!(
+            summary(&diagnostics),
+            vec!["warning: undefined variables: project"]
+        );
+    }
+}